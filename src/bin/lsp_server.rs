@@ -0,0 +1,19 @@
+// Language server for the virtual whitespace formatter over stdio, see `token_editor::lsp`
+
+use std::io;
+use std::process;
+
+use token_editor::lsp::Server;
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    match Server::default().run(&mut stdin.lock(), &mut stdout.lock()) {
+        Ok(true) => (),
+        Ok(false) => process::exit(1),
+        Err(e) => {
+            eprintln!("lsp_server: {}", e);
+            process::exit(1);
+        },
+    }
+}
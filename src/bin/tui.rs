@@ -0,0 +1,143 @@
+// Terminal front-end: `cargo run --features tui --bin tui -- <file>`. Ctrl+s saves the typed
+// text, Ctrl+q quits.
+
+use std::fs;
+use std::io::{self, Write};
+
+use crossterm::{input, queue, terminal, AlternateScreen, Attribute, Color, Goto, Hide, InputEvent, KeyEvent, SetAttr, SetFg, Show};
+
+use token_editor::keymap::KeyChord;
+use token_editor::lexer::TokenKind;
+use token_editor::tui::{Screen, Style, Tui};
+
+// converts to the key names of the web front-end
+fn chord(key: &KeyEvent) -> Option<KeyChord> {
+    let (name, ctrl, alt, shift) = match *key {
+        KeyEvent::Char('\n') => ("Enter".to_string(), false, false, false),
+        KeyEvent::Char('\t') => ("Tab".to_string(), false, false, false),
+        KeyEvent::BackTab => ("Tab".to_string(), false, false, true),
+        KeyEvent::Char(c) => (c.to_string(), false, false, false),
+        KeyEvent::Ctrl(c) => (c.to_string(), true, false, false),
+        KeyEvent::Alt(c) => (c.to_string(), false, true, false),
+        KeyEvent::Backspace => ("Backspace".to_string(), false, false, false),
+        KeyEvent::Delete => ("Delete".to_string(), false, false, false),
+        KeyEvent::Left => ("ArrowLeft".to_string(), false, false, false),
+        KeyEvent::Right => ("ArrowRight".to_string(), false, false, false),
+        KeyEvent::Up => ("ArrowUp".to_string(), false, false, false),
+        KeyEvent::Down => ("ArrowDown".to_string(), false, false, false),
+        KeyEvent::CtrlLeft => ("ArrowLeft".to_string(), true, false, false),
+        KeyEvent::CtrlRight => ("ArrowRight".to_string(), true, false, false),
+        KeyEvent::CtrlUp => ("ArrowUp".to_string(), true, false, false),
+        KeyEvent::CtrlDown => ("ArrowDown".to_string(), true, false, false),
+        KeyEvent::ShiftLeft => ("ArrowLeft".to_string(), false, false, true),
+        KeyEvent::ShiftRight => ("ArrowRight".to_string(), false, false, true),
+        KeyEvent::ShiftUp => ("ArrowUp".to_string(), false, false, true),
+        KeyEvent::ShiftDown => ("ArrowDown".to_string(), false, false, true),
+        KeyEvent::Home => ("Home".to_string(), false, false, false),
+        KeyEvent::End => ("End".to_string(), false, false, false),
+        KeyEvent::PageUp => ("PageUp".to_string(), false, false, false),
+        KeyEvent::PageDown => ("PageDown".to_string(), false, false, false),
+        KeyEvent::Esc => ("Escape".to_string(), false, false, false),
+        KeyEvent::F(n) => (format!("F{}", n), false, false, false),
+        _ => return None,
+    };
+    Some(KeyChord::new(&name, ctrl, alt, shift, false))
+}
+
+fn color(kind: TokenKind) -> Color {
+    match kind {
+        TokenKind::Keyword => Color::Magenta,
+        TokenKind::Lifetime => Color::Cyan,
+        TokenKind::Macro => Color::Blue,
+        TokenKind::Number => Color::Yellow,
+        TokenKind::Str | TokenKind::Char => Color::Green,
+        TokenKind::Comment => Color::DarkGrey,
+        _ => Color::Reset,
+    }
+}
+
+fn draw<W: Write>(out: &mut W, screen: &Screen) -> io::Result<()> {
+    queue!(out, Hide)?;
+    for row in 0..screen.height {
+        queue!(out, Goto(0, row as u16))?;
+        for col in 0..screen.width {
+            let cell = screen.cell(row, col);
+            queue!(out, SetAttr(Attribute::Reset), SetFg(Color::Reset))?;
+            match cell.style {
+                Style::Plain => (),
+                Style::Token(kind) => queue!(out, SetFg(color(kind)))?,
+                Style::Virtual => queue!(out, SetAttr(Attribute::Dim), SetFg(Color::DarkGrey))?,
+                Style::StatusBar => queue!(out, SetAttr(Attribute::Reverse))?,
+            }
+            write!(out, "{}", cell.c)?;
+        }
+    }
+    queue!(out, SetAttr(Attribute::Reset), SetFg(Color::Reset))?;
+    if let Some((row, col)) = screen.cursor {
+        queue!(out, Goto(col as u16, row as u16), Show)?;
+    }
+    out.flush()
+}
+
+fn run<W: Write>(tui: &mut Tui, out: &mut W, path: Option<&str>) -> io::Result<()> {
+    let mut screen = Screen::new(0, 0);
+    let mut events = input().read_sync();
+    loop {
+        // crossterm doesn't report resizes, so the size is checked before each redraw
+        let (width, height) = terminal().terminal_size();
+        if (width as usize, height as usize) != (screen.width, screen.height) {
+            tui.resize(width as usize, height as usize);
+            screen = Screen::new(width as usize, height as usize);
+        }
+        tui.render(&mut screen);
+        draw(out, &screen)?;
+        if tui.quit() {
+            return Ok(());
+        }
+        match events.next() {
+            Some(InputEvent::Keyboard(KeyEvent::Ctrl('s'))) => {
+                let status = match path {
+                    Some(path) => match fs::write(path, tui.content().typed_string()) {
+                        Ok(()) => format!("Saved {}", path),
+                        Err(e) => format!("Can't save {}: {}", path, e),
+                    },
+                    None => "No file to save to".to_string(),
+                };
+                tui.set_status(&status);
+            },
+            Some(InputEvent::Keyboard(key)) => {
+                if let Some(chord) = chord(&key) {
+                    tui.handle_key(&chord);
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let path = std::env::args().nth(1);
+    // a file that doesn't exist yet is created on save. Any other error ends here, an empty
+    // buffer would overwrite the file on save.
+    let text = match &path {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => {
+                eprintln!("Can't read {}: {}", path, e);
+                std::process::exit(1);
+            },
+        },
+        None => String::new(),
+    };
+    let (width, height) = terminal().terminal_size();
+    let mut tui = Tui::new(&text, width as usize, height as usize);
+
+    let mut out = io::stdout();
+    // raw mode and the alternate screen are left when `_screen` is dropped
+    let _screen = AlternateScreen::to_alternate(true)?;
+    let result = run(&mut tui, &mut out, path.as_ref().map(String::as_str));
+    queue!(out, Show)?;
+    out.flush()?;
+    result
+}
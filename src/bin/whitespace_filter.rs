@@ -0,0 +1,46 @@
+// Git clean/smudge filter, see `token_editor::filter`. Set it up with
+//
+//     git config filter.token-editor.clean "whitespace_filter clean %f"
+//     git config filter.token-editor.smudge "whitespace_filter smudge %f"
+//     echo "*.rs filter=token-editor" >> .gitattributes
+
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{self, Command};
+
+use token_editor::filter::Sidecar;
+
+fn git_dir() -> PathBuf {
+    Command::new("git").args(&["rev-parse", "--git-dir"]).output().ok()
+        .filter(|output| output.status.success())
+        .map(|output| PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+        .unwrap_or_else(|| PathBuf::from(".git"))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 3 || (args[1] != "clean" && args[1] != "smudge") {
+        eprintln!("usage: whitespace_filter (clean|smudge) <path>");
+        process::exit(2);
+    }
+    let path = &args[2];
+    let mut input = String::new();
+    if let Err(e) = io::stdin().read_to_string(&mut input) {
+        eprintln!("whitespace_filter: {}", e);
+        process::exit(1);
+    }
+
+    let sidecar = Sidecar::new(git_dir().join("token-editor"));
+    let output = if args[1] == "clean" {
+        match sidecar.clean(path, &input) {
+            Ok(canonical) => canonical,
+            Err(e) => {
+                eprintln!("whitespace_filter: can't store the layout of {}: {}", path, e);
+                process::exit(1);
+            },
+        }
+    } else {
+        sidecar.smudge(path, &input)
+    };
+    io::stdout().write_all(output.as_bytes()).expect("can't write to stdout");
+}
@@ -1,5 +1,6 @@
 
 use std::string::ToString;
+use crate::lexer::{self, Token, TokenKind};
 
 #[derive(Clone, Debug)]
 pub struct Content {
@@ -7,6 +8,9 @@ pub struct Content {
     cursor: (usize, usize),  // first element is the index of the selected whitespace element.
                              // the sectond element is the selection index within that whitespace element
     spacial_cursor: (usize, usize),
+    folds: Vec<(usize, usize)>,  // elmt indices of the `{` and `}` of folded blocks
+    cursors: Vec<(usize, usize)>,  // additional cursors, in the order they were added
+    auto_closed: Vec<usize>,  // elmt indices of closers inserted by `insert_auto_close`
 }
 
 #[derive(Clone, Debug)]
@@ -30,6 +34,43 @@ pub enum WhitespaceChar {
 
 type CursorPos = ((usize, usize), (usize, usize));
 
+// how the displayed text differs from the typed one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WhitespaceDiff {
+    Unchanged,
+    Overridden,  // typed whitespace that isn't in the formatter's layout, e.g. a space before `;`
+    Supplemented,  // virtual whitespace displayed in addition to the typed one
+}
+
+// where word motions stop besides the borders of words
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WordStop {
+    Whitespace,  // at both ends of a whitespace, like Ctrl+Arrow
+    WordStart,  // only in front of words, like Vim's `w` and `b`
+}
+
+// displayed instead of the content of a folded block
+pub const FOLD_PLACEHOLDER: &str = "⋯";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatStats {
+    pub typed_chars: usize,
+    pub displayed_chars: usize,
+}
+
+impl FormatStats {
+    // typed chars in percent of the displayed chars
+    pub fn ratio(&self) -> usize {
+        self.typed_chars * 100 / std::cmp::max(self.displayed_chars, 1)
+    }
+}
+
+impl std::fmt::Display for FormatStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Typed chars: {}, Displayed: {} ({}%)", self.typed_chars, self.displayed_chars, self.ratio())
+    }
+}
+
 
 impl WhitespaceChar {
     fn is_newline(&self) -> bool {
@@ -72,6 +113,29 @@ impl GetString for Whitespace {
     }
 }
 
+impl Whitespace {
+    // the typed chars compared with the virtual layout at the same index, followed by the
+    // virtual whitespace that's displayed in addition
+    fn diff(&self) -> Vec<(WhitespaceDiff, char)> {
+        let layout: Vec<char> = if self.virtual_newlines > 0 {
+            "\n".repeat(self.virtual_newlines).chars().chain(" ".repeat(self.virtual_spaces).chars()).collect()
+        } else {
+            " ".repeat(self.virtual_spaces).chars().collect()
+        };
+        let displayed = self.get_string();
+        displayed.chars().enumerate().map(|(k, c)| {
+            let diff = if k >= self.typed.len() {
+                WhitespaceDiff::Supplemented
+            } else if layout.get(k) == Some(&c) {
+                WhitespaceDiff::Unchanged
+            } else {
+                WhitespaceDiff::Overridden
+            };
+            (diff, c)
+        }).collect()
+    }
+}
+
 impl GetString for Elmt {
     fn get_string(&self) -> String {
         let mut s = self.whitespace.get_string();
@@ -123,34 +187,180 @@ impl Content {
             }
         });
 
-        Content {
+        let mut content = Content {
             elmts: elmts,
             cursor: (0, 0),
             spacial_cursor: (0, 0),
+            folds: vec!(),
+            cursors: vec!(),
+            auto_closed: vec!(),
+        };
+        content.separate_literals();
+        content
+    }
+
+    // whitespace inside comments and literals is part of the token, so it's stored as elmt
+    // characters there. Only the whitespace between tokens is `Whitespace` that can be virtual,
+    // e.g. a comment can't be ended by a virtual newline. Called after every edit, since typing
+    // a `"` or `//` changes what's inside a literal.
+    fn separate_literals(&mut self) {
+        let (chars, _) = self.typed_chars();
+        let mut opaque = vec![false; chars.len()];
+        for t in lexer::tokenize(&chars).into_iter().filter(|t| t.kind.is_opaque()) {
+            for k in t.start..t.end {
+                opaque[k] = true;
+            }
         }
+        let mut k = 0;
+        let mut changed = false;
+        let mut in_literal = vec!();  // whether the char of each elmt is inside a literal
+        for elmt in &self.elmts {
+            let len = elmt.whitespace.typed.len();
+            changed |= opaque[k..k + len].iter().any(|o| *o);
+            k += len;
+            if elmt.character != '\0' {
+                changed |= (elmt.character == ' ' || elmt.character == '\n') && !opaque[k];
+                in_literal.push(opaque[k]);
+                k += 1;
+            }
+        }
+        // a `"` or `//` typed in front of a fold can turn its braces into text
+        self.folds.retain(|&(open, close)| !in_literal[open] && !in_literal[close]);
+        if !changed {
+            return;
+        }
+
+        // the cursors, folds and auto-closed closers are kept at their typed offsets
+        let main = self.typed_offset(self.cursor);
+        let cursors: Vec<usize> = self.cursors.iter().map(|c| self.typed_offset(*c)).collect();
+        let folds: Vec<(usize, usize)> = self.folds.iter().map(|&(open, close)| {
+            (self.typed_offset(self.position_before_char(open)), self.typed_offset(self.position_before_char(close)))
+        }).collect();
+        let auto_closed: Vec<usize> = self.auto_closed.iter().map(|i| self.typed_offset(self.position_before_char(*i))).collect();
+
+        let mut elmts = vec!();
+        let mut typed = vec!();
+        let mut k = 0;
+        for elmt in std::mem::replace(&mut self.elmts, vec!()) {
+            for wc in elmt.whitespace.typed {
+                if opaque[k] {
+                    elmts.push(Elmt {
+                        character: if wc.is_newline() { '\n' } else { ' ' },
+                        whitespace: Whitespace { typed: std::mem::replace(&mut typed, vec!()), virtual_newlines: 0, virtual_spaces: 0 },
+                    });
+                } else {
+                    typed.push(wc);
+                }
+                k += 1;
+            }
+            let c = elmt.character;
+            if (c == ' ' || c == '\n') && !opaque[k] {
+                typed.push(if c == '\n' { WhitespaceChar::Newline } else { WhitespaceChar::Space });
+            } else {
+                elmts.push(Elmt {
+                    character: c,
+                    whitespace: Whitespace { typed: std::mem::replace(&mut typed, vec!()), ..elmt.whitespace },
+                });
+            }
+            if c != '\0' {
+                k += 1;
+            }
+        }
+        self.elmts = elmts;
+
+        self.cursor = self.cursor_at_typed_offset(main);
+        self.cursors = cursors.into_iter().map(|offset| self.cursor_at_typed_offset(offset)).collect();
+        self.folds = folds.into_iter().map(|(open, close)| {
+            (self.cursor_at_typed_offset(open).0, self.cursor_at_typed_offset(close).0)
+        }).collect();
+        self.auto_closed = auto_closed.into_iter().map(|offset| self.cursor_at_typed_offset(offset).0).collect();
+        self.spacial_cursor = self.cursor_pos().0;
     }
 
+    // aligns the elmts with the formatted text. The chars are matched with the same diff as the
+    // tokens in `restore_layout`, the formatter only adds whitespace and a few chars (e.g.
+    // trailing commas). Comments and literals are matched as a whole, so whitespace inside them
+    // is never virtual. A changed one, e.g. a comment the formatter wrapped into several ones,
+    // gets the whitespace in front of the first of the formatted comments.
     pub fn update_virtual_whitespace_2(&mut self, formatted_input: &str) {
-        let mut chars = formatted_input.chars().peekable();
-        
+        let formatted: Vec<char> = formatted_input.chars().collect();
+        let units = alignment_units(&self.tokens(), self.elmts.len() - 1, |i| self.elmts[i].character);
+        let formatted_units = alignment_units(&lexer::tokenize(&formatted), formatted.len(), |i| formatted[i]);
+        let texts = |units: &[(usize, String)]| units.iter().map(|(_, s)| s.clone()).collect::<Vec<_>>();
+        let pairs = matching_items(&texts(&units), &texts(&formatted_units));
+
+        // elmts and the positions in the formatted text they start at
+        let mut starts = vec!();
+        let (mut k, mut l) = (0, 0);
+        for &(pk, pl) in &pairs {
+            if k < pk && l < pl {
+                starts.push((units[k].0, formatted_units[l].0));
+            }
+            starts.push((units[pk].0, formatted_units[pl].0));
+            k = pk + 1;
+            l = pl + 1;
+        }
+        if k < units.len() && l < formatted_units.len() {
+            starts.push((units[k].0, formatted_units[l].0));
+        }
+        starts.push((self.elmts.len() - 1, formatted.len()));
+
         for elmt in &mut self.elmts {
-            let mut virtual_newlines = 0;
-            let mut virtual_spaces = 0;
-            while let Some(vc) = chars.peek() {
-                match vc {
-                    '\n' => { virtual_newlines += 1; virtual_spaces = 0; chars.next(); },
-                    ' ' => { virtual_spaces += 1; chars.next(); },
-                    x if x == &elmt.character => { chars.next(); break; },
-                    ',' => { println!("Ignoring comma"); chars.next(); },
-                    _ => {
-                        break;
-                    }
-                }
+            elmt.whitespace.virtual_newlines = 0;
+            elmt.whitespace.virtual_spaces = 0;
+        }
+        for (i, pos) in starts {
+            let (newlines, spaces) = whitespace_before(&formatted, pos);
+            self.elmts[i].whitespace.virtual_newlines = newlines;
+            self.elmts[i].whitespace.virtual_spaces = spaces;
+        }
+    }
+
+    // replaces the whitespace with the one of `layout`, e.g. an older version of the text with
+    // the developer's whitespace. The tokens of both are matched with a diff, the whitespace
+    // between two tokens is taken over if both are unchanged. Returns false if that would
+    // join tokens.
+    pub fn restore_layout(&mut self, layout: &str) -> bool {
+        let layout: Vec<char> = layout.chars().collect();
+        let layout_tokens = lexer::tokenize(&layout);
+        let tokens = self.tokens();
+        let layout_texts: Vec<String> = layout_tokens.iter().map(|t| layout[t.start..t.end].iter().collect()).collect();
+        let texts: Vec<String> = tokens.iter().map(|t| self.chars_of(t.start, t.end)).collect();
+        let pairs = matching_items(&texts, &layout_texts);
+
+        let original = self.elmts.clone();
+        let whitespace = |from: usize, to: usize| layout[from..to].iter()
+            .filter(|c| **c == ' ' || **c == '\n')
+            .map(|c| if *c == '\n' { WhitespaceChar::Newline } else { WhitespaceChar::Space })
+            .collect::<Vec<_>>();
+        for (n, &(k, l)) in pairs.iter().enumerate() {
+            let from = if k == 0 && l == 0 {
+                Some(0)
+            } else if n > 0 && k > 0 && l > 0 && pairs[n - 1] == (k - 1, l - 1) {
+                Some(layout_tokens[l - 1].end)
+            } else {
+                None
+            };
+            if let Some(from) = from {
+                self.elmts[tokens[k].start].whitespace.typed = whitespace(from, layout_tokens[l].start);
             }
-            
-            elmt.whitespace.virtual_newlines = virtual_newlines;
-            elmt.whitespace.virtual_spaces = virtual_spaces;
         }
+        let last = self.elmts.len() - 1;
+        if !tokens.is_empty() && pairs.last() == Some(&(tokens.len() - 1, layout_tokens.len() - 1)) {
+            self.elmts[last].whitespace.typed = whitespace(layout_tokens[layout_tokens.len() - 1].end, layout.len());
+        }
+        self.clear_virtual_whitespace();
+
+        let typed = |elmts: &[Elmt]| {
+            let s: String = elmts.iter().map(|e| e.get_string()).collect();
+            token_texts(&s.chars().collect::<Vec<_>>())
+        };
+        if typed(&self.elmts) != typed(&original) {
+            self.elmts = original;
+            return false;
+        }
+        self.clamp_cursors();
+        true
     }
 
     pub fn from_strings(typed: &str, visible: &str) -> Content {
@@ -160,16 +370,30 @@ impl Content {
     }
 
     pub fn cursor_pos(&self) -> CursorPos {
-        let s: String = self.elmts.iter().take(self.cursor.0).map(|x| x.get_string()).collect();
+        self.cursor_pos_at(self.cursor)
+    }
+
+    // display position of an arbitrary cursor position
+    pub fn cursor_pos_at(&self, cursor: (usize, usize)) -> CursorPos {
+        let s: String = self.display_strings().into_iter().take(cursor.0).collect();
         let mut line = s.chars().filter(|x| x == &'\n').count();
         let mut col = s.chars().rev().take_while(|x| x != &'\n').count();
+
+        // the whitespace in front of the `}` of a fold is replaced by the placeholder
+        let ranges = self.fold_ranges();
+        if ranges.iter().any(|&(_, close)| close == cursor.0) {
+            let pos = (line, col + FOLD_PLACEHOLDER.chars().count());
+            return (pos, pos);
+        } else if ranges.iter().any(|&(open, close)| open < cursor.0 && cursor.0 < close) {
+            return ((line, col), (line, col));
+        }
         
-        let virtual_spaces = self.elmts[self.cursor.0].whitespace.virtual_spaces;
-        let virtual_newlines = self.elmts[self.cursor.0].whitespace.virtual_newlines;
+        let virtual_spaces = self.elmts[cursor.0].whitespace.virtual_spaces;
+        let virtual_newlines = self.elmts[cursor.0].whitespace.virtual_newlines;
         let virtual_end = (line + virtual_newlines, if virtual_newlines==0 {col} else {0} + virtual_spaces);
 
-        let typed = &self.elmts[self.cursor.0].whitespace.typed;
-        for wc in typed.iter().take(self.cursor.1) {
+        let typed = &self.elmts[cursor.0].whitespace.typed;
+        for wc in typed.iter().take(cursor.1) {
             match wc {
                 WhitespaceChar::Space => col += 1,
                 WhitespaceChar::Newline => {col = 0; line += 1;},
@@ -177,7 +401,7 @@ impl Content {
         }
 
         let start = (line, col);
-        let end = if self.cursor.1 == self.elmts[self.cursor.0].whitespace.get_num_cursor_positions() - 1 {
+        let end = if cursor.1 == self.elmts[cursor.0].whitespace.get_num_cursor_positions() - 1 {
             std::cmp::max(start, virtual_end)
         } else {
             start.clone()
@@ -186,6 +410,198 @@ impl Content {
         (start, end)
     }
 
+    // line and column of the cursor in the typed text (virtual whitespace not counted)
+    pub fn typed_cursor_pos(&self) -> (usize, usize) {
+        let mut line = 0;
+        let mut col = 0;
+        let chars = self.elmts.iter().take(self.cursor.0).flat_map(|e| {
+            let ws = e.whitespace.typed.iter().map(|x| if x.is_newline() { '\n' } else { ' ' });
+            ws.chain(std::iter::once(e.character))
+        });
+        let typed = self.elmts[self.cursor.0].whitespace.typed.iter()
+            .take(self.cursor.1)
+            .map(|x| if x.is_newline() { '\n' } else { ' ' });
+        for c in chars.chain(typed) {
+            if c == '\n' {
+                line += 1;
+                col = 0;
+            } else {
+                col += 1;
+            }
+        }
+        (line, col)
+    }
+
+    // true if the cursor covers virtual whitespace, i.e. there's no single display position for it
+    pub fn cursor_in_virtual_whitespace(&self) -> bool {
+        let (start, end) = self.cursor_pos();
+        start != end
+    }
+
+    pub fn cursor(&self) -> (usize, usize) {
+        self.cursor
+    }
+
+    pub fn set_cursor(&mut self, cursor: (usize, usize)) {
+        let i = std::cmp::min(cursor.0, self.elmts.len() - 1);
+        self.cursor = (i, std::cmp::min(cursor.1, self.elmts[i].whitespace.get_num_cursor_positions() - 1));
+        self.spacial_cursor = self.cursor_pos().0;
+    }
+
+    pub fn num_elmts(&self) -> usize {
+        self.elmts.len()
+    }
+
+    // `'\0'` for the last elmt
+    pub fn char_at(&self, i: usize) -> char {
+        self.elmts[i].character
+    }
+
+    // the cursor position directly in front of the character of elmt `i`
+    pub fn position_before_char(&self, i: usize) -> (usize, usize) {
+        (i, self.elmts[i].whitespace.typed.len())
+    }
+
+    pub fn end_position(&self) -> (usize, usize) {
+        self.position_before_char(self.elmts.len() - 1)
+    }
+
+    // true if the character of elmt `i` belongs to the same word as the one of elmt `i - 1`.
+    // Any whitespace in between, typed or virtual, ends a word.
+    pub fn continues_word(&self, i: usize) -> bool {
+        if i == 0 || i >= self.elmts.len() - 1 {
+            return false;
+        }
+        let class = |c: char| if lexer::is_ident_continue(c) { 0 } else if c.is_whitespace() { 1 } else { 2 };
+        self.elmts[i].whitespace.get_string().is_empty()
+            && class(self.elmts[i].character) == class(self.elmts[i - 1].character)
+    }
+
+    // the characters of the elmts in `start..end`, without the whitespace in front of them.
+    // Whitespace inside comments and literals is included, it's stored as elmt characters.
+    pub fn chars_of(&self, start: usize, end: usize) -> String {
+        self.elmts[start..end].iter().map(|e| e.character).filter(|c| *c != '\0').collect()
+    }
+
+    // the elmt whose character is right of the cursor or, at the very end, the last character
+    pub fn elmt_at_cursor(&self) -> usize {
+        if self.cursor.0 == self.elmts.len() - 1 && self.cursor.0 > 0 {
+            self.cursor.0 - 1
+        } else {
+            self.cursor.0
+        }
+    }
+
+    // display area covered by the elmts in `start..end`
+    pub fn elmt_range_area(&self, start: usize, end: usize) -> CursorPos {
+        (self.cursor_pos_at(self.position_before_char(start)).0, self.cursor_pos_at((end, 0)).0)
+    }
+
+    pub fn typed_string(&self) -> String {
+        self.typed_chars().0.into_iter().collect()
+    }
+
+    // number of typed chars in front of a cursor position
+    pub fn typed_offset(&self, cursor: (usize, usize)) -> usize {
+        self.elmts.iter().take(cursor.0).map(|e| e.whitespace.typed.len() + 1).sum::<usize>() + cursor.1
+    }
+
+    pub fn cursor_at_typed_offset(&self, mut offset: usize) -> (usize, usize) {
+        for (i, elmt) in self.elmts.iter().enumerate() {
+            if offset <= elmt.whitespace.typed.len() {
+                return (i, offset);
+            }
+            offset -= elmt.whitespace.typed.len() + 1;
+        }
+        self.end_position()
+    }
+
+    // deletes the typed chars between two cursor positions and returns them
+    pub fn delete_range(&mut self, start: (usize, usize), end: (usize, usize)) -> String {
+        let (start, end) = (std::cmp::min(start, end), std::cmp::max(start, end));
+        let start_offset = self.typed_offset(start);
+        let end_offset = self.typed_offset(end);
+        let deleted = self.typed_string().chars().skip(start_offset).take(end_offset - start_offset).collect();
+        self.set_cursor(start);
+        self.edit_at_cursor(|content| {
+            for _ in start_offset..end_offset {
+                content.delete_char();
+            }
+        });
+        self.separate_literals();
+        deleted
+    }
+
+    pub fn insert_str(&mut self, s: &str) {
+        self.edit_at_cursor(|content| {
+            for c in s.chars() {
+                content.insert_char(c);
+            }
+        });
+        self.separate_literals();
+    }
+
+    // cursor position for a display position, i.e. the position `cursor_down` and friends would
+    // pick. `None` if there's no such line.
+    pub fn cursor_for_display_pos(&self, target: (usize, usize)) -> Option<(usize, usize)> {
+        let mut line = 0;
+        let mut col = 0;
+        let mut cursor = None;
+        let ranges = self.fold_ranges();
+        for (i, chars) in self.display_strings().into_iter().enumerate() {
+            for (j, c) in chars.chars().enumerate() {
+                if line == target.0 {
+                    cursor = Some(self.display_cursor(&ranges, i, j));
+                }
+                if (line == target.0 && col >= target.1) || line > target.0 {
+                    return cursor;
+                }
+                if c == '\n' {
+                    col = 0;
+                    line += 1;
+                } else {
+                    col += 1;
+                }
+            }
+        }
+        if line == target.0 {
+            Some(self.end_position())
+        } else {
+            cursor
+        }
+    }
+
+    // moves the cursor to the start of a display line. Returns false if there's no such line.
+    pub fn go_to_display_line(&mut self, line: usize) -> bool {
+        match self.cursor_for_display_pos((line, 0)) {
+            Some(cursor) => {
+                self.set_cursor(cursor);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // like `go_to_display_line`, but counting the lines of the typed text
+    pub fn go_to_typed_line(&mut self, line: usize) -> bool {
+        let offset = if line == 0 {
+            Some(0)
+        } else {
+            self.typed_string().chars().enumerate()
+                .filter(|(_, c)| *c == '\n')
+                .nth(line - 1)
+                .map(|(k, _)| k + 1)
+        };
+        match offset {
+            Some(offset) => {
+                let cursor = self.cursor_at_typed_offset(offset);
+                self.set_cursor(cursor);
+                true
+            },
+            None => false,
+        }
+    }
+
     pub fn cursor_pos_2(&self) -> (CursorPos, (usize, usize)) {
         let cursor_pos = self.cursor_pos();
         let small = if self.spacial_cursor.0 == (cursor_pos.0).0 {
@@ -202,6 +618,7 @@ impl Content {
         } else if self.cursor.0 > 0 {
             self.cursor = (self.cursor.0 - 1, self.elmts[self.cursor.0 - 1].whitespace.get_num_cursor_positions() - 1);
         }
+        self.skip_folds(false);
         self.spacial_cursor = self.cursor_pos().1;
     }
 
@@ -211,6 +628,7 @@ impl Content {
         } else if self.cursor.0 < self.elmts.len() - 1 {
             self.cursor = (self.cursor.0 + 1, 0);
         }
+        self.skip_folds(true);
         self.spacial_cursor = self.cursor_pos().0;
     }
 
@@ -218,10 +636,11 @@ impl Content {
         let target = (self.spacial_cursor.0+1, self.spacial_cursor.1);
         let mut line = 0;
         let mut col = 0;
-        for (i, chars) in self.elmts.iter().map(|x| x.get_string()).enumerate() {
+        let ranges = self.fold_ranges();
+        for (i, chars) in self.display_strings().into_iter().enumerate() {
             for (j, c) in chars.chars().enumerate() {
                 if line == target.0 {
-                    self.cursor = (i, std::cmp::min(j, self.elmts[i].whitespace.get_num_cursor_positions()-1));
+                    self.cursor = self.display_cursor(&ranges, i, j);
                 }
                 if (line == target.0 && col >= target.1) || line > target.0 {
                     self.spacial_cursor = target;
@@ -247,10 +666,11 @@ impl Content {
         };
         let mut line = 0;
         let mut col = 0;
-        for (i, chars) in self.elmts.iter().map(|x| x.get_string()).enumerate() {
+        let ranges = self.fold_ranges();
+        for (i, chars) in self.display_strings().into_iter().enumerate() {
             for (j, c) in chars.chars().enumerate() {
                 if line == target.0 {
-                    self.cursor = (i, std::cmp::min(j, self.elmts[i].whitespace.get_num_cursor_positions()-1));
+                    self.cursor = self.display_cursor(&ranges, i, j);
                 }
                 if (line == target.0 && col >= target.1) || line > target.0 {
                     self.spacial_cursor = target;
@@ -265,17 +685,21 @@ impl Content {
             }
         }
 
-        panic!("this shouldn't be possible!");
+        // no char at or behind the target column, so the target is on the last line, e.g. an empty
+        // last line or an empty document: the cursor goes to the end
+        self.cursor = (self.elmts.len()-1, self.elmts[self.elmts.len()-1].whitespace.get_num_cursor_positions()-1);
+        self.spacial_cursor = self.cursor_pos().0;
     }
 
     pub fn cursor_home(&mut self) {
         let target = (self.spacial_cursor.0, 0);
         let mut line = 0;
         let mut col = 0;
-        for (i, chars) in self.elmts.iter().map(|x| x.get_string()).enumerate() {
+        let ranges = self.fold_ranges();
+        for (i, chars) in self.display_strings().into_iter().enumerate() {
             for (j, c) in chars.chars().enumerate() {
                 if line == target.0 {
-                    self.cursor = (i, std::cmp::min(j, self.elmts[i].whitespace.get_num_cursor_positions()-1));
+                    self.cursor = self.display_cursor(&ranges, i, j);
                 }
                 if (line == target.0 && col >= target.1) || line > target.0 {
                     self.spacial_cursor = target;
@@ -290,37 +714,730 @@ impl Content {
             }
         }
 
-        panic!("this shouldn't be possible!");
+        // the target is behind the last char, on an empty last line
+        self.cursor = (self.elmts.len()-1, self.elmts[self.elmts.len()-1].whitespace.get_num_cursor_positions()-1);
+        self.spacial_cursor = self.cursor_pos().0;
+    }
+
+    pub fn cursor_end(&mut self) {
+        let target = (self.spacial_cursor.0, self.spacial_cursor.1);
+        let mut line = 0;
+        let mut col = 0;
+        let mut curr_col = 0;
+        let ranges = self.fold_ranges();
+        for (i, chars) in self.display_strings().into_iter().enumerate() {
+            for (j, c) in chars.chars().enumerate() {
+                if line == target.0 {
+                    self.cursor = self.display_cursor(&ranges, i, j);
+                    curr_col = col;
+                }
+                if line > target.0 {
+                    self.spacial_cursor = (self.spacial_cursor.0, curr_col);
+                    return;
+                }
+                if c == '\n' {
+                    col = 0;
+                    line += 1;
+                } else {
+                    col += 1;
+                }
+            }
+        }
+        self.cursor = (self.elmts.len()-1, self.elmts[self.elmts.len()-1].whitespace.get_num_cursor_positions()-1);
+        self.spacial_cursor = self.cursor_pos().0;
+    }
+
+    // Word motions stop at the borders of identifier, punctuation and whitespace runs. A whole
+    // whitespace (typed and virtual) is one run, so there are no stops within it. With
+    // `WordStop::WordStart` the whitespace is skipped, like Vim's `w` and `b` do.
+    pub fn word_right_position(&self, stop: WordStop) -> (usize, usize) {
+        let (i, j) = self.cursor;
+        if j < self.elmts[i].whitespace.typed.len() || i == self.elmts.len() - 1 {
+            return self.position_before_char(i);
+        }
+        let mut k = i + 1;
+        while self.continues_word(k) {
+            k += 1;
+        }
+        match stop {
+            WordStop::Whitespace => (k, 0),
+            WordStop::WordStart => self.position_before_char(k),
+        }
+    }
+
+    pub fn word_left_position(&self, stop: WordStop) -> (usize, usize) {
+        let (i, j) = self.cursor;
+        if j > 0 && stop == WordStop::Whitespace {
+            return (i, 0);
+        }
+        if i == 0 {
+            return (0, 0);
+        }
+        let mut k = i - 1;
+        while self.continues_word(k) {
+            k -= 1;
+        }
+        self.position_before_char(k)
+    }
+
+    // in front of the last character of the word at or behind the cursor, like Vim's `e`
+    pub fn word_end_position(&self) -> (usize, usize) {
+        let (i, j) = self.cursor;
+        let last = self.elmts.len() - 1;
+        let mut k = if j < self.elmts[i].whitespace.typed.len() { i } else { i + 1 };
+        if k >= last {
+            return self.end_position();
+        }
+        while self.continues_word(k + 1) {
+            k += 1;
+        }
+        self.position_before_char(k)
+    }
+
+    pub fn cursor_word_right(&mut self) {
+        let pos = self.word_right_position(WordStop::Whitespace);
+        self.set_cursor(pos);
+    }
+
+    pub fn cursor_word_left(&mut self) {
+        let pos = self.word_left_position(WordStop::Whitespace);
+        self.set_cursor(pos);
+        self.spacial_cursor = self.cursor_pos().1;
+    }
+
+    // returns false if nothing is deleted, since that would join tokens
+    pub fn delete_word_forward(&mut self) -> bool {
+        let end = self.word_right_position(WordStop::Whitespace);
+        if self.range_deletion_merges_tokens(self.cursor, end) {
+            return false;
+        }
+        self.delete_range(self.cursor, end);
+        true
+    }
+
+    // returns false if nothing is deleted, since that would join tokens
+    pub fn delete_word_backward(&mut self) -> bool {
+        let start = self.word_left_position(WordStop::Whitespace);
+        if self.range_deletion_merges_tokens(start, self.cursor) {
+            return false;
+        }
+        self.delete_range(start, self.cursor);
+        self.spacial_cursor = self.cursor_pos().1;
+        true
+    }
+
+    // indices of elmts that are bracket characters in code, i.e. not in comments or literals
+    fn code_brackets(&self) -> Vec<usize> {
+        self.tokens().into_iter()
+            .filter(|t| t.kind == TokenKind::Punct)
+            .flat_map(|t| t.start..t.end)
+            .filter(|i| "()[]{}<>".contains(self.elmts[*i].character))
+            .collect()
+    }
+
+    // `<` that opens generic arguments or parameters, e.g. in `Vec<u8>`, `fn a<T>` or `::<`
+    fn is_generic_open(&self, tokens: &[Token], index: usize) -> bool {
+        let prev = |n: usize| index.checked_sub(n).map(|k| (tokens[k].kind, self.chars_of(tokens[k].start, tokens[k].end)));
+        match (prev(1), prev(2)) {
+            (Some((_, ref p)), _) if p == "::" => true,
+            (Some((TokenKind::Keyword, ref p)), _) => p == "impl" || p == "for",
+            (Some((TokenKind::Ident, ref p)), _) if p.starts_with(char::is_uppercase) => true,
+            (Some((TokenKind::Ident, _)), Some((TokenKind::Keyword, ref k))) => {
+                ["fn", "struct", "enum", "trait", "type", "union"].contains(&k.as_ref())
+            },
+            _ => false,
+        }
+    }
+
+    // pairs of matching brackets as elmt indices, opening bracket first
+    pub fn bracket_pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = vec!();
+        let mut stack: Vec<usize> = vec!();
+        let brackets = self.code_brackets();
+        for &i in &brackets {
+            match self.elmts[i].character {
+                '(' | '[' | '{' => stack.push(i),
+                c @ ')' | c @ ']' | c @ '}' => {
+                    let open = match c { ')' => '(', ']' => '[', _ => '{' };
+                    if let Some(pos) = stack.iter().rposition(|k| self.elmts[*k].character == open) {
+                        pairs.push((stack[pos], i));
+                        stack.truncate(pos);
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        // angle brackets are only matched in generics, where they can't contain `;`, `{` and such
+        let tokens = self.tokens();
+        for (index, t) in tokens.iter().enumerate() {
+            if t.kind != TokenKind::Punct || self.chars_of(t.start, t.end) != "<" || !self.is_generic_open(&tokens, index) {
+                continue;
+            }
+            let mut depth = 0;
+            for u in &tokens[index..] {
+                let text = self.chars_of(u.start, u.end);
+                if u.kind == TokenKind::Punct && (text.contains(|c| ";{}".contains(c)) || text == "&&" || text == "||") {
+                    break;
+                }
+                if u.kind != TokenKind::Punct || text == "->" || text == "=>" {
+                    continue;
+                }
+                let close = text.chars().enumerate().find_map(|(k, c)| {
+                    match c {
+                        '<' => depth += 1,
+                        '>' => depth -= 1,
+                        _ => (),
+                    }
+                    if depth == 0 { Some(u.start + k) } else { None }
+                });
+                if let Some(close) = close {
+                    pairs.push((t.start, close));
+                    break;
+                }
+            }
+        }
+        pairs.sort();
+        pairs
+    }
+
+    // the bracket directly right of the cursor or, if there's none, the one directly left of it,
+    // together with its match
+    pub fn matching_bracket_at_cursor(&self) -> Option<(usize, usize)> {
+        let (i, j) = self.cursor;
+        let mut candidates = vec!();
+        if j == self.elmts[i].whitespace.typed.len() {
+            candidates.push(i);
+        }
+        if j == 0 && i > 0 {
+            candidates.push(i - 1);
+        }
+        let pairs = self.bracket_pairs();
+        candidates.into_iter().filter_map(|k| {
+            pairs.iter().find_map(|&(open, close)| {
+                if open == k { Some((k, close)) } else if close == k { Some((k, open)) } else { None }
+            })
+        }).next()
+    }
+
+    pub fn jump_to_matching_bracket(&mut self) {
+        if let Some((_, other)) = self.matching_bracket_at_cursor() {
+            let pos = self.position_before_char(other);
+            self.set_cursor(pos);
+        }
+    }
+
+    // like `insert`, but inserts the closing bracket or quote for openers and types over
+    // closers right of the cursor that were inserted this way
+    pub fn insert_auto_close(&mut self, c: char) {
+        let (i, j) = self.cursor;
+        let directly_before = j == self.elmts[i].whitespace.typed.len();
+        if directly_before && self.elmts[i].character == c && self.auto_closed.contains(&i) {
+            self.auto_closed.retain(|k| *k != i);
+            self.set_cursor((i + 1, 0));
+            return;
+        }
+        let closer = match c {
+            '(' => Some(')'),
+            '[' => Some(']'),
+            '{' => Some('}'),
+            '"' => Some('"'),
+            _ => None,
+        };
+        let next = if directly_before { self.elmts[i].character } else { ' ' };
+        self.insert(c);
+        if let Some(closer) = closer {
+            if !lexer::is_ident_continue(next) && next != '"' {
+                self.insert(closer);
+                self.cursor_left();
+                self.auto_closed.push(self.cursor.0);
+            }
+        }
+    }
+
+    // the number of typed chars
+    fn typed_len(&self) -> usize {
+        self.typed_offset(self.end_position())
+    }
+
+    // the additional cursors (the main cursor is `cursor()`)
+    pub fn extra_cursors(&self) -> &[(usize, usize)] {
+        &self.cursors
+    }
+
+    // display positions of the additional cursors, see `cursor_pos_2`
+    pub fn extra_cursor_positions(&self) -> Vec<(CursorPos, (usize, usize))> {
+        self.cursors.iter().map(|c| {
+            let pos = self.cursor_pos_at(*c);
+            (pos, pos.0)
+        }).collect()
+    }
+
+    pub fn add_cursor(&mut self, cursor: (usize, usize)) -> bool {
+        let i = std::cmp::min(cursor.0, self.elmts.len() - 1);
+        let cursor = (i, std::cmp::min(cursor.1, self.elmts[i].whitespace.get_num_cursor_positions() - 1));
+        if cursor == self.cursor || self.cursors.contains(&cursor) {
+            return false;
+        }
+        self.cursors.push(cursor);
+        true
+    }
+
+    pub fn clear_extra_cursors(&mut self) {
+        self.cursors.clear();
+    }
+
+    // applies `f` (an edit or motion of the main cursor) to every cursor, from the first to the
+    // last one. The cursors are tracked as typed offsets, so edits in front of a cursor shift it.
+    pub fn for_each_cursor<F: FnMut(&mut Content)>(&mut self, mut f: F) {
+        if self.cursors.is_empty() {
+            f(self);
+            return;
+        }
+        let main = self.typed_offset(self.cursor);
+        // taken out, so the edits don't shift them as well, see `edit_at_cursor`
+        let cursors = std::mem::replace(&mut self.cursors, vec!());
+        let mut offsets: Vec<usize> = cursors.iter().map(|c| self.typed_offset(*c)).collect();
+        offsets.push(main);
+        offsets.sort();
+        offsets.dedup();
+
+        let mut shift: isize = 0;
+        let mut new_main = 0;
+        let mut new_offsets = vec!();
+        for offset in offsets {
+            let len_before = self.typed_len() as isize;
+            let cursor = self.cursor_at_typed_offset((offset as isize + shift) as usize);
+            self.set_cursor(cursor);
+            f(self);
+            shift += self.typed_len() as isize - len_before;
+            let new_offset = self.typed_offset(self.cursor);
+            if offset == main {
+                new_main = new_offset;
+            }
+            new_offsets.push(new_offset);
+        }
+
+        // cursors that ran into each other are merged
+        let len = self.typed_len();
+        let main_cursor = self.cursor_at_typed_offset(std::cmp::min(new_main, len));
+        self.set_cursor(main_cursor);
+        for offset in new_offsets {
+            let cursor = self.cursor_at_typed_offset(std::cmp::min(offset, len));
+            self.add_cursor(cursor);
+        }
+    }
+
+    // applies `edit`, which inserts or deletes typed chars at the main cursor. The additional
+    // cursors keep their typed offsets: behind the edit they're shifted, in deleted text they
+    // end up where it was.
+    fn edit_at_cursor<F: FnOnce(&mut Content)>(&mut self, edit: F) {
+        if self.cursors.is_empty() {
+            edit(self);
+            return;
+        }
+        let offset = |content: &Content, (i, j): (usize, usize)| {
+            content.typed_offset((i, std::cmp::min(j, content.elmts[i].whitespace.typed.len())))
+        };
+        let offsets: Vec<usize> = self.cursors.iter().map(|c| offset(self, *c)).collect();
+        let (before, len_before) = (offset(self, self.cursor), self.typed_len());
+        edit(self);
+        let start = std::cmp::min(before, offset(self, self.cursor));
+        let len = self.typed_len();
+        let cursors: Vec<(usize, usize)> = offsets.into_iter().map(|k| {
+            let k = if k < start {
+                k
+            } else if len >= len_before {
+                k + (len - len_before)
+            } else {
+                std::cmp::max(start, k.saturating_sub(len_before - len))
+            };
+            self.cursor_at_typed_offset(k)
+        }).collect();
+        // cursors that ran into each other are merged
+        self.cursors.clear();
+        for cursor in cursors {
+            self.add_cursor(cursor);
+        }
+    }
+
+    // adds a cursor at the next occurrence of the word at the last added cursor, at the same
+    // position within the word. Returns false if there's no other occurrence.
+    pub fn add_cursor_at_next_occurrence(&mut self) -> bool {
+        let last = *self.cursors.last().unwrap_or(&self.cursor);
+        let text: Vec<char> = self.typed_string().chars().collect();
+        let offset = self.typed_offset(last);
+        let is_word = |k: usize| k < text.len() && lexer::is_ident_continue(text[k]);
+        let mut start = offset;
+        while start > 0 && is_word(start - 1) {
+            start -= 1;
+        }
+        let mut end = offset;
+        while is_word(end) {
+            end += 1;
+        }
+        if start == end {
+            return false;
+        }
+        let word = &text[start..end];
+        let occurrence = (end..text.len()).chain(0..start).find(|&k| {
+            text[k..].starts_with(word)
+                && (k == 0 || !is_word(k - 1))
+                && !is_word(k + word.len())
+        });
+        match occurrence {
+            Some(k) => {
+                let cursor = self.cursor_at_typed_offset(k + offset - start);
+                self.add_cursor(cursor)
+            },
+            None => false,
+        }
+    }
+
+    // adds a cursor on the display line above or below the last added cursor
+    pub fn add_cursor_vertically(&mut self, down: bool) -> bool {
+        let last = *self.cursors.last().unwrap_or(&self.cursor);
+        let (line, col) = self.cursor_pos_at(last).0;
+        let line = if down {
+            line + 1
+        } else if line > 0 {
+            line - 1
+        } else {
+            return false;
+        };
+        match self.cursor_for_display_pos((line, col)) {
+            Some(cursor) => self.add_cursor(cursor),
+            None => false,
+        }
+    }
+
+    // column selection: one cursor per display line from the main cursor's line to `target`,
+    // all in the column of `target`. Lines that are too short get no cursor.
+    pub fn select_column(&mut self, target: (usize, usize)) {
+        let (line, _) = self.cursor_pos().0;
+        let lines = if line <= target.0 { line..=target.0 } else { target.0..=line };
+        self.cursors.clear();
+        let mut main = None;
+        for l in lines {
+            let cursor = match self.cursor_for_display_pos((l, target.1)) {
+                Some(cursor) if self.cursor_pos_at(cursor).1 .1 >= target.1 => cursor,
+                _ => continue,
+            };
+            if main.is_none() {
+                main = Some(cursor);
+                self.set_cursor(cursor);
+            } else {
+                self.add_cursor(cursor);
+            }
+        }
+    }
+
+    // inserts `pad` typed spaces in front of the `k`th displayed whitespace char of elmt `i` and
+    // returns the cursor position behind them. If that's inside the virtual whitespace, the whole
+    // whitespace of the elmt becomes typed. Otherwise, text inserted there would be followed by
+    // the complete virtual whitespace again.
+    fn materialize(&mut self, i: usize, k: usize, pad: usize) -> (usize, usize) {
+        let ws = &mut self.elmts[i].whitespace;
+        if k > ws.typed.len() {
+            ws.typed = ws.get_string().chars()
+                .map(|c| if c == '\n' { WhitespaceChar::Newline } else { WhitespaceChar::Space })
+                .collect();
+            ws.virtual_newlines = 0;
+            ws.virtual_spaces = 0;
+        }
+        for _ in 0..pad {
+            ws.typed.insert(k, WhitespaceChar::Space);
+        }
+        (i, k + pad)
+    }
+
+    // a cursor position whose display position starts at `target`, so that inserted text shows
+    // up there. Virtual whitespace in front of it
+    // is made typed and short lines are padded with typed spaces (unless `pad` is false, then
+    // the end of the line is used). `None` if there's no such line.
+    pub fn materialize_display_pos(&mut self, target: (usize, usize), pad: bool) -> Option<(usize, usize)> {
+        let (mut line, mut col) = (0, 0);
+        for i in 0..self.elmts.len() {
+            let ws: Vec<char> = self.elmts[i].whitespace.get_string().chars().collect();
+            for k in 0..=ws.len() {
+                if (line, col) == target {
+                    return Some(self.materialize(i, k, 0));
+                }
+                if k == ws.len() {
+                    break;
+                }
+                if ws[k] == '\n' {
+                    if line == target.0 {
+                        // the line is too short
+                        return Some(self.materialize(i, k, if pad { target.1 - col } else { 0 }));
+                    }
+                    line += 1;
+                    col = 0;
+                } else {
+                    col += 1;
+                }
+            }
+            if self.elmts[i].character != '\0' {
+                col += 1;
+            } else if line == target.0 {
+                return Some(self.materialize(i, ws.len(), if pad { target.1 - col } else { 0 }));
+            }
+        }
+        None
+    }
+
+    // replaces the rectangle between two display positions with `text` on every line and leaves
+    // a cursor behind the text on every line. Columns are display columns, so whitespace
+    // (including virtual whitespace) inside the rectangle becomes typed before it's replaced.
+    pub fn block_replace(&mut self, from: (usize, usize), to: (usize, usize), text: &str) {
+        self.unfold_all();
+        self.cursors.clear();
+        let (top, bottom) = (std::cmp::min(from.0, to.0), std::cmp::max(from.0, to.0));
+        let (left, right) = (std::cmp::min(from.1, to.1), std::cmp::max(from.1, to.1));
+
+        // typed offsets of the rectangle on every line, all in the materialized text
+        let mut ranges = vec!();
+        for line in top..=bottom {
+            let start = match self.materialize_display_pos((line, left), true) {
+                Some(start) => start,
+                None => break,
+            };
+            let end = self.materialize_display_pos((line, right), false).unwrap_or(start);
+            ranges.push((self.typed_offset(start), std::cmp::max(self.typed_offset(start), self.typed_offset(end))));
+        }
+        // the offsets of earlier lines stay valid when deleting from the bottom
+        for &(start, end) in ranges.iter().rev() {
+            let (start, end) = (self.cursor_at_typed_offset(start), self.cursor_at_typed_offset(end));
+            self.delete_range(start, end);
+        }
+        let mut deleted = 0;
+        for (k, &(start, end)) in ranges.iter().enumerate() {
+            let cursor = self.cursor_at_typed_offset(start - deleted);
+            if k == 0 {
+                self.set_cursor(cursor);
+            } else {
+                self.add_cursor(cursor);
+            }
+            deleted += end - start;
+        }
+        self.for_each_cursor(|c| c.insert_str(text));
+    }
+
+    // makes the virtual whitespace in front of the elmts `start..end` typed, so the current
+    // layout is kept even without formatting
+    pub fn materialize_whitespace(&mut self, start: usize, end: usize) {
+        let end = std::cmp::min(end, self.elmts.len());
+        for i in start..end {
+            let ws = &mut self.elmts[i].whitespace;
+            ws.typed = ws.get_string().chars()
+                .map(|c| if c == '\n' { WhitespaceChar::Newline } else { WhitespaceChar::Space })
+                .collect();
+            ws.virtual_newlines = 0;
+            ws.virtual_spaces = 0;
+        }
+    }
+
+    // strips the typed whitespace in front of the elmts `start..end` down to what's needed to
+    // keep the tokens apart, e.g. `let x` keeps its space and a line comment its newline.
+    // Whitespace inside comments and literals is part of them and isn't touched.
+    pub fn dematerialize_whitespace(&mut self, start: usize, end: usize) -> bool {
+        let (chars, elmt_indices) = self.typed_chars();
+        let tokens = lexer::tokenize(&chars);
+        let original: Vec<Vec<WhitespaceChar>> = self.elmts.iter().map(|e| e.whitespace.typed.clone()).collect();
+        let text = |t: &Token| chars[t.start..t.end].iter().collect::<String>();
+        let in_range = |i: usize| start <= i && i < end;
+
+        for (k, b) in tokens.iter().enumerate() {
+            let i = elmt_indices[b.start];
+            if !in_range(i) || self.elmts[i].whitespace.typed.is_empty() {
+                continue;
+            }
+            // nothing is needed in front of the first token
+            let a = match k.checked_sub(1) {
+                Some(k) => &tokens[k],
+                None => {
+                    self.elmts[i].whitespace.typed.clear();
+                    continue;
+                },
+            };
+            let (a_text, b_text) = (text(a), text(b));
+            let separates = |ws: &str| {
+                let lexed = lexer::tokenize_str(&format!("{}{}{}", a_text, ws, b_text));
+                lexed.len() == 2 && lexed[0].kind == a.kind && lexed[0].end == a.end - a.start && lexed[1].kind == b.kind
+            };
+            let minimal = if separates("") {
+                vec!()
+            } else if separates(" ") {
+                vec!(WhitespaceChar::Space)
+            } else if separates("\n") {
+                vec!(WhitespaceChar::Newline)
+            } else {
+                continue;
+            };
+            self.elmts[i].whitespace.typed = minimal;
+        }
+        // nothing is needed behind the last token
+        let last = self.elmts.len() - 1;
+        if in_range(last) {
+            self.elmts[last].whitespace.typed.clear();
+        }
+
+        // the tokens are checked pairwise above, make sure the whole text still lexes the same
+        if token_texts(&self.typed_chars().0) != token_texts(&chars) {
+            for (elmt, typed) in self.elmts.iter_mut().zip(original) {
+                elmt.whitespace.typed = typed;
+            }
+            return false;
+        }
+        self.clamp_cursors();
+        true
+    }
+
+    // the displayed text has to lex like the typed one, otherwise the virtual whitespace splits
+    // or joins tokens, e.g. `return1` displayed as `return 1`
+    pub fn check_virtual_whitespace(&self) -> Result<(), String> {
+        let tokens = |s: String| token_texts(&s.chars().collect::<Vec<_>>());
+        let (typed, displayed) = (tokens(self.typed_string()), tokens(self.get_string()));
+        match typed.iter().zip(&displayed).find(|(a, b)| a != b) {
+            Some((a, b)) => Err(format!("`{}` is displayed as `{}`", a.1, b.1)),
+            None if typed.len() != displayed.len() => Err("the displayed text has a different number of tokens".to_string()),
+            None => Ok(()),
+        }
+    }
+
+    // the typed text with only the whitespace that's needed to keep the tokens apart, as it's
+    // saved. Loading it with `from_string` and formatting restores the layout.
+    pub fn to_minimal_string(&self) -> Result<String, String> {
+        self.check_virtual_whitespace()?;
+        let mut minimal = self.clone();
+        if !minimal.dematerialize_whitespace(0, minimal.num_elmts()) {
+            return Err("whitespace can't be stripped without changing the tokens".to_string());
+        }
+        Ok(minimal.typed_string())
+    }
+
+    // moves cursors that point behind the typed whitespace of their elmt in front of the char
+    fn clamp_cursors(&mut self) {
+        let elmts = &self.elmts;
+        let clamp = |(i, j): (usize, usize)| (i, std::cmp::min(j, elmts[i].whitespace.typed.len()));
+        self.cursor = clamp(self.cursor);
+        self.cursors = self.cursors.iter().map(|c| clamp(*c)).collect();
+        self.spacial_cursor = self.cursor_pos().0;
+    }
+
+    // folded blocks as elmt indices of their `{` and `}`, outermost first
+    fn fold_ranges(&self) -> Vec<(usize, usize)> {
+        let mut ranges = self.folds.clone();
+        ranges.sort();
+        ranges
+    }
+
+    // the displayed text of every elmt. Folded blocks show `FOLD_PLACEHOLDER` in front of the `}`.
+    fn display_strings(&self) -> Vec<String> {
+        let ranges = self.fold_ranges();
+        self.elmts.iter().enumerate().map(|(i, elmt)| {
+            if ranges.iter().any(|&(open, close)| open < i && i < close) {
+                String::new()
+            } else if ranges.iter().any(|&(_, close)| close == i) {
+                format!("{}{}", FOLD_PLACEHOLDER, elmt.character)
+            } else {
+                elmt.get_string()
+            }
+        }).collect()
+    }
+
+    pub fn display_string(&self) -> String {
+        self.display_strings().concat()
+    }
+
+    // cursor position for the `j`th displayed char of elmt `i`. Within a fold, that's the
+    // position in front of the `}`.
+    fn display_cursor(&self, ranges: &[(usize, usize)], i: usize, j: usize) -> (usize, usize) {
+        if ranges.iter().any(|&(_, close)| close == i) {
+            self.position_before_char(i)
+        } else {
+            (i, std::cmp::min(j, self.elmts[i].whitespace.get_num_cursor_positions() - 1))
+        }
+    }
+
+    // moves a cursor that's hidden in a fold in front of its `}`, or its `{` when moving backwards
+    fn skip_folds(&mut self, forward: bool) {
+        let (i, j) = self.cursor;
+        let hidden = self.fold_ranges().into_iter().find(|&(open, close)| {
+            (open < i && i < close) || (i == close && j < self.elmts[close].whitespace.typed.len())
+        });
+        if let Some((open, close)) = hidden {
+            self.cursor = self.position_before_char(if forward { close } else { open });
+        }
     }
 
-    pub fn cursor_end(&mut self) {
-        let target = (self.spacial_cursor.0, self.spacial_cursor.1);
-        let mut line = 0;
-        let mut col = 0;
-        let mut curr_col = 0;
-        for (i, chars) in self.elmts.iter().map(|x| x.get_string()).enumerate() {
-            for (j, c) in chars.chars().enumerate() {
-                if line == target.0 {
-                    self.cursor = (i, std::cmp::min(j, self.elmts[i].whitespace.get_num_cursor_positions()-1));
-                    curr_col = col;
-                }
-                if line > target.0 {
-                    self.spacial_cursor = (self.spacial_cursor.0, curr_col);
-                    return;
-                }
-                if c == '\n' {
-                    col = 0;
-                    line += 1;
-                } else {
-                    col += 1;
-                }
+    fn unfold_where<F: Fn(usize, usize) -> bool>(&mut self, f: F) {
+        self.folds.retain(|&(open, close)| !f(open, close));
+    }
+
+    // keeps the folds and auto-closed closers anchored to their elmts when an elmt is inserted
+    // or removed at `i`
+    fn shift_elmt_indices(&mut self, i: usize, inserted: bool) {
+        let shift = |k: usize| if k < i { Some(k) } else if inserted { Some(k + 1) } else if k == i { None } else { Some(k - 1) };
+        self.folds = self.folds.iter().filter_map(|&(open, close)| Some((shift(open)?, shift(close)?))).collect();
+        self.auto_closed = self.auto_closed.iter().filter_map(|&k| shift(k)).collect();
+    }
+
+    pub fn fold(&mut self, open: usize) {
+        self.fold_blocks(&[open]);
+    }
+
+    // folds the blocks of the `{`s at the elmt indices `opens`
+    pub fn fold_blocks(&mut self, opens: &[usize]) {
+        let mut opens = opens.to_vec();
+        opens.sort();
+        for (open, close) in self.bracket_pairs() {
+            let folded = self.folds.iter().any(|f| f.0 == open);
+            if self.elmts[open].character == '{' && !folded && opens.binary_search(&open).is_ok() {
+                self.folds.push((open, close));
             }
         }
-        self.cursor = (self.elmts.len()-1, self.elmts[self.elmts.len()-1].whitespace.get_num_cursor_positions()-1);
+        self.skip_folds(true);
         self.spacial_cursor = self.cursor_pos().0;
     }
 
+    // folds or unfolds the innermost block around the cursor
+    pub fn toggle_fold(&mut self) {
+        let i = self.elmt_at_cursor();
+        let block = self.bracket_pairs().into_iter()
+            .filter(|&(open, close)| self.elmts[open].character == '{' && open <= i && i <= close)
+            .last();
+        if let Some((open, _)) = block {
+            if self.folds.iter().any(|f| f.0 == open) {
+                self.folds.retain(|f| f.0 != open);
+            } else {
+                self.fold(open);
+            }
+        }
+    }
+
+    pub fn unfold_all(&mut self) {
+        self.folds.clear();
+    }
+
     pub fn insert(&mut self, c: char) {
+        self.edit_at_cursor(|content| content.insert_char(c));
+        self.separate_literals();
+    }
+
+    pub fn backspace(&mut self) {
+        self.edit_at_cursor(Content::backspace_char);
+        self.separate_literals();
+    }
+
+    pub fn delete(&mut self) {
+        self.edit_at_cursor(Content::delete_char);
+        self.separate_literals();
+    }
+
+    fn insert_char(&mut self, c: char) {
+        let i = self.cursor.0;
+        self.unfold_where(|open, close| open < i && i <= close);
         // check for whitespace
         if c == '\n' || c == ' ' {
             let typed_len = self.elmts[self.cursor.0].whitespace.typed.len();
@@ -344,11 +1461,14 @@ impl Content {
         };
         self.elmts[self.cursor.0].whitespace.typed = ws_right;
         self.elmts.insert(self.cursor.0, new_elmt);
+        self.shift_elmt_indices(self.cursor.0, true);
         self.cursor = (self.cursor.0 + 1, 0);
         self.spacial_cursor = self.cursor_pos().0;
     }
 
-    pub fn backspace(&mut self) {
+    fn backspace_char(&mut self) {
+        let i = self.cursor.0;
+        self.unfold_where(|open, close| open < i && i <= close + 1);
         if self.cursor.1 > 0 {
             if self.cursor.1 <= self.elmts[self.cursor.0].whitespace.typed.len() {
                 self.elmts[self.cursor.0].whitespace.typed.remove(self.cursor.1 - 1);
@@ -372,12 +1492,15 @@ impl Content {
             self.elmts[self.cursor.0].whitespace = ws_new;
             let cursor_new = (self.cursor.0 - 1, self.elmts[self.cursor.0 - 1].whitespace.get_num_cursor_positions() - 1);
             self.elmts.remove(self.cursor.0 - 1);
+            self.shift_elmt_indices(self.cursor.0 - 1, false);
             self.cursor = cursor_new;
             self.spacial_cursor = self.cursor_pos().1;
         }
     }
 
-    pub fn delete(&mut self) {
+    fn delete_char(&mut self) {
+        let i = self.cursor.0;
+        self.unfold_where(|open, close| open <= i && i <= close);
         if self.cursor.1 < self.elmts[self.cursor.0].whitespace.typed.len() {
             self.elmts[self.cursor.0].whitespace.typed.remove(self.cursor.1);
         } else if self.cursor.0 < self.elmts.len() - 1 {
@@ -396,11 +1519,70 @@ impl Content {
             };
             self.elmts[self.cursor.0 + 1].whitespace = ws_new;
             self.elmts.remove(self.cursor.0);
-            self.cursor = (self.cursor.0, 0);
+            self.shift_elmt_indices(self.cursor.0, false);
+            // the cursor stays behind the typed whitespace that was left of the deleted char
             self.spacial_cursor = self.cursor_pos().0;
         }
     }
 
+    // the typed whitespace char that `backspace` (or `delete`) would remove
+    fn typed_whitespace_to_delete(&self, backward: bool) -> Option<(usize, usize)> {
+        let (i, j) = self.cursor;
+        let len = self.elmts[i].whitespace.typed.len();
+        if backward && j > 0 && j <= len {
+            Some((i, j - 1))
+        } else if !backward && j < len {
+            Some((i, j))
+        } else {
+            None
+        }
+    }
+
+    // whether deleting typed whitespace at the cursor joins the tokens around it, e.g. `return 1`
+    // becoming `return1` or code moving into a line comment. The virtual whitespace would still
+    // display them apart.
+    pub fn deletion_merges_tokens(&self, backward: bool) -> bool {
+        match self.typed_whitespace_to_delete(backward) {
+            Some((i, k)) => self.range_deletion_merges_tokens((i, k), (i, k + 1)),
+            None => false,
+        }
+    }
+
+    // whether the typed whitespace between two cursor positions keeps tokens apart that would
+    // join if the range is deleted. Deleting other chars may join tokens, e.g. the `+` of `a+b`.
+    pub fn range_deletion_merges_tokens(&self, start: (usize, usize), end: (usize, usize)) -> bool {
+        let (start, end) = (std::cmp::min(start, end), std::cmp::max(start, end));
+        let (start, end) = (self.typed_offset(start), self.typed_offset(end));
+        let (chars, elmt_indices) = self.typed_chars();
+        // whitespace chars belong to the elmt they're preceeding, its char comes last
+        let last = self.elmts.len() - 1;
+        let is_whitespace = |k: usize| elmt_indices[k] == last || elmt_indices.get(k + 1) == Some(&elmt_indices[k]);
+        let without_whitespace: Vec<char> = (0..chars.len())
+            .filter(|&k| k < start || k >= end || is_whitespace(k))
+            .map(|k| chars[k])
+            .collect();
+        let deleted: Vec<char> = chars[..start].iter().chain(&chars[end..]).cloned().collect();
+        token_texts(&deleted) != token_texts(&without_whitespace)
+    }
+
+    // returns false if the whitespace is kept, see `deletion_merges_tokens`
+    pub fn backspace_keeping_tokens(&mut self) -> bool {
+        if self.deletion_merges_tokens(true) {
+            return false;
+        }
+        self.backspace();
+        true
+    }
+
+    // returns false if the whitespace is kept, see `deletion_merges_tokens`
+    pub fn delete_keeping_tokens(&mut self) -> bool {
+        if self.deletion_merges_tokens(false) {
+            return false;
+        }
+        self.delete();
+        true
+    }
+
     pub fn clear_virtual_whitespace(&mut self) {
         for e in &mut self.elmts {
             e.whitespace.virtual_newlines = 0;
@@ -408,7 +1590,168 @@ impl Content {
         }
     }
 
-    pub fn update_virtual_whitespace(&mut self, window_width: usize) -> String {
+    // the typed text (without virtual whitespace) and, for every char of it, the index of the
+    // elmt it belongs to. Whitespace chars belong to the elmt they're preceeding.
+    fn typed_chars(&self) -> (Vec<char>, Vec<usize>) {
+        let mut chars = vec!();
+        let mut elmt_indices = vec!();
+        for (i, elmt) in self.elmts.iter().enumerate() {
+            for wc in &elmt.whitespace.typed {
+                chars.push(if wc.is_newline() { '\n' } else { ' ' });
+                elmt_indices.push(i);
+            }
+            if elmt.character != '\0' {
+                chars.push(elmt.character);
+                elmt_indices.push(i);
+            }
+        }
+        (chars, elmt_indices)
+    }
+
+    // tokens of the typed text. `start` and `end` are elmt indices (`end` is exclusive).
+    pub fn tokens(&self) -> Vec<Token> {
+        let (chars, elmt_indices) = self.typed_chars();
+        lexer::tokenize(&chars).into_iter().map(|t| Token {
+            kind: t.kind,
+            start: elmt_indices[t.start],
+            end: elmt_indices[t.end - 1] + 1,
+        }).collect()
+    }
+
+    // token kind for every elmt
+    pub fn token_kinds(&self) -> Vec<Option<TokenKind>> {
+        let mut kinds = vec![None; self.elmts.len()];
+        for t in self.tokens() {
+            for k in &mut kinds[t.start..t.end] {
+                *k = Some(t.kind);
+            }
+        }
+        kinds
+    }
+
+    // the displayed text, split into runs of the same token kind. Only the typed text
+    // is lexed, so the result doesn't depend on the virtual whitespace.
+    pub fn highlight(&self) -> Vec<(Option<TokenKind>, String)> {
+        // like `token_kinds`, and whether the whitespace of an elmt is inside its token (e.g. in
+        // comments and strings), so it's part of that token
+        let mut kinds = vec![None; self.elmts.len()];
+        let mut inside_token = vec![false; self.elmts.len()];
+        for t in self.tokens() {
+            for k in t.start..t.end {
+                kinds[k] = Some(t.kind);
+                inside_token[k] = k > t.start;
+            }
+        }
+        let mut spans: Vec<(Option<TokenKind>, String)> = vec!();
+        let mut push = |kind: Option<TokenKind>, s: String| {
+            if s.is_empty() {
+                return;
+            }
+            match spans.last_mut() {
+                Some((k, last)) if *k == kind => last.push_str(&s),
+                _ => spans.push((kind, s)),
+            }
+        };
+        let ranges = self.fold_ranges();
+        for (i, elmt) in self.elmts.iter().enumerate() {
+            if ranges.iter().any(|&(open, close)| open < i && i < close) {
+                continue;
+            }
+            if ranges.iter().any(|&(_, close)| close == i) {
+                push(None, FOLD_PLACEHOLDER.to_string());
+                push(kinds[i], elmt.character.to_string());
+                continue;
+            }
+            let ws_kind = if inside_token[i] { kinds[i] } else { None };
+            push(ws_kind, elmt.whitespace.get_string());
+            if elmt.character != '\0' {
+                push(kinds[i], elmt.character.to_string());
+            }
+        }
+        spans
+    }
+
+    // the displayed text (without folds), split into runs of typed whitespace overridden by
+    // the layout, virtual whitespace supplementing the typed one and everything else
+    pub fn whitespace_diff(&self) -> Vec<(WhitespaceDiff, String)> {
+        let mut spans: Vec<(WhitespaceDiff, String)> = vec!();
+        let mut push = |diff: WhitespaceDiff, c: char| {
+            match spans.last_mut() {
+                Some((d, last)) if *d == diff => last.push(c),
+                _ => spans.push((diff, c.to_string())),
+            }
+        };
+        for elmt in &self.elmts {
+            for (diff, c) in elmt.whitespace.diff() {
+                push(diff, c);
+            }
+            if elmt.character != '\0' {
+                push(WhitespaceDiff::Unchanged, elmt.character);
+            }
+        }
+        spans
+    }
+
+    // the virtual whitespace that's displayed in addition to the typed one, as the typed
+    // position (line and column in chars) it's displayed at and its newlines and spaces
+    pub fn virtual_padding(&self) -> Vec<((usize, usize), usize, usize)> {
+        let mut padding = vec!();
+        let (mut line, mut col) = (0, 0);
+        let advance = |c: char, line: &mut usize, col: &mut usize| if c == '\n' {
+            *line += 1;
+            *col = 0;
+        } else {
+            *col += 1;
+        };
+        for elmt in &self.elmts {
+            for wc in &elmt.whitespace.typed {
+                advance(if wc.is_newline() { '\n' } else { ' ' }, &mut line, &mut col);
+            }
+            let added: String = elmt.whitespace.get_string().chars().skip(elmt.whitespace.typed.len()).collect();
+            if !added.is_empty() {
+                let newlines = added.chars().filter(|c| *c == '\n').count();
+                let spaces = added.chars().rev().take_while(|c| *c == ' ').count();
+                padding.push(((line, col), newlines, spaces));
+            }
+            if elmt.character != '\0' {
+                advance(elmt.character, &mut line, &mut col);
+            }
+        }
+        padding
+    }
+
+    // for every displayed line, the typed line (counting typed newlines only) that starts on it.
+    // Lines that are started by a virtual newline are continuations and get `None`.
+    pub fn line_numbers(&self) -> Vec<Option<usize>> {
+        let mut lines = vec!(Some(0));
+        let mut typed_line = 0;
+        let ranges = self.fold_ranges();
+        for (i, elmt) in self.elmts.iter().enumerate() {
+            let ws = &elmt.whitespace;
+            let num_typed_newlines = ws.typed.iter().filter(|x| x.is_newline()).count();
+            // newlines inside comments and literals are elmt characters
+            let num_char_newlines = if elmt.character == '\n' { 1 } else { 0 };
+            // folded lines are skipped, but still counted as typed lines
+            if ranges.iter().any(|&(open, close)| open < i && i <= close) {
+                typed_line += num_typed_newlines + num_char_newlines;
+                continue;
+            }
+            for _ in 0..num_typed_newlines {
+                typed_line += 1;
+                lines.push(Some(typed_line));
+            }
+            for _ in num_typed_newlines..ws.virtual_newlines {
+                lines.push(None);
+            }
+            for _ in 0..num_char_newlines {
+                typed_line += 1;
+                lines.push(Some(typed_line));
+            }
+        }
+        lines
+    }
+
+    pub fn update_virtual_whitespace(&mut self, window_width: usize) -> Option<FormatStats> {
         // get string (without virtual whitespace)
         let mut clone = self.clone();
         clone.clear_virtual_whitespace();
@@ -423,9 +1766,12 @@ impl Content {
                     self.elmts[self.cursor.0].whitespace.get_num_cursor_positions() - 1
                 );
                 self.spacial_cursor = self.cursor_pos().0;
-                format!("Typed chars: {}, Displayed: {} ({}%)", s.len(), res.len(), s.len()*100/res.len())
+                Some(FormatStats {
+                    typed_chars: s.len(),
+                    displayed_chars: res.len(),
+                })
             },
-            None => "error".to_string()
+            None => None
         }
     }
 }
@@ -454,9 +1800,134 @@ pub fn prettify_code(input: String, window_width: usize) -> Option<String> {
     Some(String::from_utf8(buf).unwrap())
 }
 
+// the diff in `matching_items` looks for this many changes at a time, which takes a few MB
+const MAX_DIFF_CHANGES: isize = 1000;
+
+// index pairs of equal items of `a` and `b`, in order: a longest common subsequence, or close to
+// one if there are very many changes. It's found with Myers' diff, which is fast when only a few
+// items changed, like when formatting.
+fn matching_items(a: &[String], b: &[String]) -> Vec<(usize, usize)> {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..].iter().rev().zip(b[prefix..].iter().rev()).take_while(|(x, y)| x == y).count();
+    let (a_mid, b_mid) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|k| (k, k)).collect();
+    let (mut x, mut y) = (0, 0);
+    while (x, y) != (a_mid.len(), b_mid.len()) {
+        let (path, (dx, dy)) = diff_path(&a_mid[x..], &b_mid[y..], MAX_DIFF_CHANGES);
+        pairs.extend(path.into_iter().map(|(i, j)| (prefix + x + i, prefix + y + j)));
+        x += dx;
+        y += dy;
+    }
+    pairs.extend((0..suffix).rev().map(|k| (a.len() - 1 - k, b.len() - 1 - k)));
+    pairs
+}
+
+// the index pairs of equal items on the shortest path from the start of `a` and `b` to their
+// end, and the end. If that takes more than `max_d` changes, the path ends where `max_d`
+// changes get furthest instead.
+fn diff_path(a: &[String], b: &[String], max_d: isize) -> (Vec<(usize, usize)>, (usize, usize)) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    // `v[k + offset]` is the furthest `x` reached on the diagonal `k = x - y` with `d` changes.
+    // `trace[d]` keeps the diagonals `-d..=d` of it to walk back the path.
+    let offset = max_d + 1;
+    let at = |k: isize| (k + offset) as usize;
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace: Vec<Vec<isize>> = vec!();
+    let mut end = None;
+    'search: for d in 0..=max_d {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) { v[at(k + 1)] } else { v[at(k - 1)] + 1 };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                end = Some((d, x, y));
+                break 'search;
+            }
+        }
+        trace.push(v[at(-d)..=at(d)].to_vec());
+    }
+    let (d_end, mut x, mut y) = end.unwrap_or_else(|| {
+        let x = (-max_d..=max_d).step_by(2)
+            .map(|k| (v[at(k)], v[at(k)] - k))
+            .filter(|&(x, y)| x <= n && y <= m)
+            .max_by_key(|&(x, y)| x + y)
+            .unwrap_or((0, 0));
+        (max_d, x.0, x.1)
+    });
+    let end = (x as usize, y as usize);
+
+    let mut path = vec!();
+    for d in (1..=d_end).rev() {
+        let prev = &trace[d as usize - 1];
+        let v = |k: isize| prev[(k + d - 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v(k - 1) < v(k + 1)) { k + 1 } else { k - 1 };
+        let (prev_x, prev_y) = (v(prev_k), v(prev_k) - prev_k);
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            path.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    while x > 0 && y > 0 {
+        x -= 1;
+        y -= 1;
+        path.push((x as usize, y as usize));
+    }
+    path.reverse();
+    (path, end)
+}
+
+// what `update_virtual_whitespace_2` aligns: comments and literals as a whole, other chars one
+// by one, whitespace not at all. The start index and the text of every unit.
+fn alignment_units(tokens: &[Token], len: usize, char_at: impl Fn(usize) -> char) -> Vec<(usize, String)> {
+    let mut opaque = tokens.iter().filter(|t| t.kind.is_opaque()).peekable();
+    let mut units = vec!();
+    let mut i = 0;
+    while i < len {
+        if let Some(t) = opaque.peek().filter(|t| t.start == i) {
+            units.push((i, (t.start..t.end).map(&char_at).collect()));
+            i = t.end;
+            opaque.next();
+            continue;
+        }
+        if !char_at(i).is_whitespace() {
+            units.push((i, char_at(i).to_string()));
+        }
+        i += 1;
+    }
+    units
+}
+
+// the number of newlines and the spaces on the last line of the whitespace in front of `pos`
+fn whitespace_before(chars: &[char], pos: usize) -> (usize, usize) {
+    let mut start = pos;
+    while start > 0 && (chars[start - 1] == ' ' || chars[start - 1] == '\n') {
+        start -= 1;
+    }
+    let newlines = chars[start..pos].iter().filter(|c| **c == '\n').count();
+    let spaces = chars[start..pos].iter().rev().take_while(|c| **c == ' ').count();
+    (newlines, spaces)
+}
+
+// kinds and texts of the tokens, to check whether whitespace changes split or join them
+fn token_texts(chars: &[char]) -> Vec<(TokenKind, String)> {
+    lexer::tokenize(chars).into_iter()
+        .map(|t| (t.kind, chars[t.start..t.end].iter().collect()))
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test() {
@@ -602,5 +2073,569 @@ mod tests {
         assert_eq!(&content.get_string(), "a\n  ");
     }
 
-    
+    #[test]
+    fn test_highlight_ignores_virtual_whitespace() {
+        let typed = "fn a(){//x y\n1}";
+        let mut c = Content::from_string(typed);
+        let kinds = c.token_kinds();
+        c.update_virtual_whitespace_2("fn a() {\n    //x y\n    1\n}");
+        assert_eq!(kinds, c.token_kinds());
+        assert_eq!(c.highlight()[0], (Some(TokenKind::Keyword), "fn".to_string()));
+        assert!(c.highlight().contains(&(Some(TokenKind::Comment), "//x y".to_string())));
+    }
+
+    #[test]
+    fn test_line_numbers() {
+        let typed = "fn a(){\nlet x=1;}";
+        let visible = "fn a() {\n    let x = 1;\n}";
+        let c = Content::from_strings(&typed, &visible);
+        assert_eq!(c.line_numbers(), vec!(Some(0), Some(1), None));
+        assert_eq!(c.line_numbers().len(), c.get_string().lines().count());
+
+        let c = Content::from_strings("a\n\n\nb", "a\nb");
+        assert_eq!(c.line_numbers(), vec!(Some(0), Some(1), Some(2), Some(3)));
+
+        // the newlines of a comment are part of its token
+        let c = Content::from_strings("/* a\nb\n*/fn a(){}", "/* a\nb\n*/\nfn a() {}");
+        assert_eq!(c.line_numbers(), vec!(Some(0), Some(1), Some(2), None));
+        assert_eq!(c.line_numbers().len(), c.get_string().lines().count());
+    }
+
+    #[test]
+    fn test_typed_cursor_pos() {
+        let mut c = Content::from_strings("a(){\nb}", "a() {\n    b\n}");
+        for _ in 0..4 {
+            c.cursor_right();
+        }
+        assert_eq!(c.typed_cursor_pos(), (0, 4));
+        assert!(!c.cursor_in_virtual_whitespace());
+        c.cursor_right();
+        assert_eq!(c.typed_cursor_pos(), (1, 0));
+        assert!(c.cursor_in_virtual_whitespace());
+        assert_eq!(c.cursor_pos(), ((1, 0), (1, 4)));
+    }
+
+    #[test]
+    fn test_word_motion() {
+        let mut c = Content::from_strings("let  x=a.b;", "let x = a.b;");
+        let mut stops = vec!();
+        for _ in 0..9 {
+            c.cursor_word_right();
+            stops.push(c.typed_offset(c.cursor()));
+        }
+        assert_eq!(stops, vec!(3, 5, 6, 7, 8, 9, 10, 11, 11));
+        c.cursor_word_left();
+        assert_eq!(c.typed_offset(c.cursor()), 10);
+        c.set_cursor(c.cursor_at_typed_offset(5));
+        c.cursor_word_left();
+        assert_eq!(c.typed_offset(c.cursor()), 3);
+        c.cursor_word_left();
+        assert_eq!(c.typed_offset(c.cursor()), 0);
+    }
+
+    #[test]
+    fn test_delete_word() {
+        let mut c = Content::from_strings("let x=foo;", "let x = foo;");
+        c.set_cursor(c.cursor_at_typed_offset(9));
+        c.delete_word_backward();
+        assert_eq!(&c.get_string(), "let x = ;");
+        // the virtual whitespace of both sides is merged, like `backspace` does
+        c.delete_word_backward();
+        assert_eq!(&c.get_string(), "let x  ;");
+        c.set_cursor((0, 0));
+        c.delete_word_forward();
+        assert_eq!(&c.get_string(), " x  ;");
+        c.delete_word_forward();
+        assert_eq!(&c.typed_string(), "x;");
+    }
+
+    #[test]
+    fn test_delete_range_after_typed_whitespace() {
+        let mut c = Content::from_string("let  xy=1;");
+        let start = c.cursor_at_typed_offset(5);
+        let end = c.cursor_at_typed_offset(7);
+        assert_eq!(&c.delete_range(start, end), "xy");
+        assert_eq!(&c.typed_string(), "let  =1;");
+        assert_eq!(c.typed_offset(c.cursor()), 5);
+    }
+
+    #[test]
+    fn test_bracket_pairs() {
+        let c = Content::from_string("fn a<T>(x: Vec<Vec<T>>) -> bool { x.len() < 2 && \"(\" != \")\" }");
+        let pairs: Vec<String> = c.bracket_pairs().into_iter()
+            .map(|(a, b)| format!("{}{}", c.char_at(a), c.char_at(b)))
+            .collect();
+        assert_eq!(pairs, vec!("<>", "()", "<>", "<>", "{}", "()"));
+    }
+
+    #[test]
+    fn test_matching_bracket_across_virtual_whitespace() {
+        let mut c = Content::from_strings("fn a(){x}", "fn a() {\n    x\n}");
+        c.set_cursor(c.position_before_char(5));
+        assert_eq!(c.matching_bracket_at_cursor(), Some((5, 7)));
+        c.jump_to_matching_bracket();
+        assert_eq!(c.cursor(), c.position_before_char(7));
+    }
+
+    #[test]
+    fn test_auto_close() {
+        let mut c = Content::from_strings("fn a(){}", "fn a() {}");
+        c.set_cursor(c.position_before_char(5));
+        c.insert_auto_close('{');
+        c.insert_auto_close('(');
+        assert_eq!(&c.typed_string(), "fn a(){()}{}");
+        c.insert_auto_close(')');
+        c.insert_auto_close('}');
+        c.insert_auto_close('"');
+        assert_eq!(&c.typed_string(), "fn a(){()}\"\"{}");
+        c.insert_auto_close('x');
+        c.insert_auto_close('"');
+        assert_eq!(&c.typed_string(), "fn a(){()}\"x\"{}");
+        assert_eq!(c.cursor(), c.position_before_char(12));
+        // closers that weren't inserted automatically aren't typed over
+        c.set_cursor(c.position_before_char(13));
+        c.insert_auto_close('}');
+        assert_eq!(&c.typed_string(), "fn a(){()}\"x\"{}}");
+    }
+
+    #[test]
+    fn test_go_to_line() {
+        let mut c = Content::from_strings("fn a(){\nx;y;}", "fn a() {\n    x;\n    y;\n}");
+        assert!(c.go_to_display_line(2));
+        assert_eq!(c.char_at(c.elmt_at_cursor()), 'y');
+        assert!(c.go_to_typed_line(1));
+        assert_eq!(c.cursor(), (6, 1));  // behind the typed newline
+        assert_eq!(c.char_at(c.elmt_at_cursor()), 'x');
+        assert!(!c.go_to_typed_line(2));
+        assert!(!c.go_to_display_line(4));
+    }
+
+    #[test]
+    fn test_fold() {
+        let typed = "fn a(){let x=1;x}\nfn b(){}";
+        let mut c = Content::from_strings(typed, "fn a() {\n    let x = 1;\n    x\n}\nfn b() {}");
+        c.set_cursor(c.position_before_char(5));  // `{`
+        c.toggle_fold();
+        assert_eq!(&c.display_string(), "fn a() {⋯}\nfn b() {}");
+        assert_eq!(c.line_numbers(), vec!(Some(0), Some(1)));
+        assert_eq!(c.cursor_pos(), ((0, 6), (0, 7)));
+        c.cursor_down();
+        assert_eq!(c.elmt_at_cursor(), 20);  // `{` of `fn b`
+        c.cursor_up();
+        c.cursor_end();
+        assert_eq!(c.cursor_pos().0, (0, 10));
+
+        // folds survive reformatting and edits in front of them
+        c.update_virtual_whitespace_2("fn a() { let x = 1; x }\nfn b() {}");
+        c.set_cursor((0, 0));
+        c.insert('p');
+        assert_eq!(&c.display_string(), "pfn a() {⋯}\nfn b() {}");
+
+        // the cursor can't get inside a fold
+        c.set_cursor(c.position_before_char(6));
+        c.cursor_right();
+        assert_eq!(c.char_at(c.elmt_at_cursor()), '}');
+        c.cursor_left();
+        assert_eq!(c.char_at(c.elmt_at_cursor()), '{');
+
+        // typing at a fold unfolds it
+        c.cursor_right();
+        c.insert('y');
+        assert_eq!(&c.display_string(), "pfn a() { let x = 1; xy }\nfn b() {}");
+
+        // a fold is dropped when its braces become part of a literal
+        let mut c = Content::from_string("a(){x}");
+        c.fold(3);
+        assert_eq!(&c.display_string(), "a(){⋯}");
+        c.set_cursor((0, 0));
+        c.insert('"');
+        assert_eq!(&c.display_string(), "\"a(){x}");
+    }
+
+    #[test]
+    fn test_multiple_cursors() {
+        let mut c = Content::from_strings("let a=1;\nlet ab=a;", "let a = 1;\nlet ab = a;");
+        c.set_cursor(c.position_before_char(3));  // `a`
+        assert!(c.add_cursor_at_next_occurrence());
+        assert!(!c.add_cursor_at_next_occurrence());  // `ab` isn't an occurrence
+        c.for_each_cursor(|c| c.insert('x'));
+        assert_eq!(&c.typed_string(), "let xa=1;\nlet ab=xa;");
+        c.for_each_cursor(|c| c.cursor_right());
+        c.for_each_cursor(|c| c.backspace());
+        assert_eq!(&c.typed_string(), "let x=1;\nlet ab=x;");
+        c.for_each_cursor(|c| c.delete());
+        assert_eq!(&c.typed_string(), "let x1;\nlet ab=x");
+        assert_eq!(c.extra_cursors().len(), 1);
+
+        // cursors that meet are merged
+        c.for_each_cursor(|c| c.cursor_end());
+        c.clear_extra_cursors();
+        c.set_cursor((0, 0));
+        c.add_cursor((1, 0));
+        c.for_each_cursor(|c| c.backspace());
+        assert_eq!(&c.typed_string(), "et x1;\nlet ab=x");
+        assert!(c.extra_cursors().is_empty());
+    }
+
+    #[test]
+    fn test_column_selection() {
+        let mut c = Content::from_string("abc\nd\nefg");
+        c.set_cursor((0, 0));
+        c.select_column((2, 2));
+        c.for_each_cursor(|c| c.insert('|'));
+        assert_eq!(&c.typed_string(), "ab|c\nd\nef|g");
+        c.clear_extra_cursors();
+        c.set_cursor((0, 0));
+        assert!(c.add_cursor_vertically(true));
+        assert!(c.add_cursor_vertically(true));
+        assert!(!c.add_cursor_vertically(true));
+        c.for_each_cursor(|c| c.insert('>'));
+        assert_eq!(&c.typed_string(), ">ab|c\n>d\n>ef|g");
+    }
+
+    #[test]
+    fn test_materialize_display_pos() {
+        let mut c = Content::from_strings("fn a(){x}", "fn a() {\n    x\n}");
+        let cursor = c.materialize_display_pos((1, 2), true).unwrap();
+        assert_eq!(cursor, (6, 3));
+        assert_eq!(c.cursor_pos_at(cursor).0, (1, 2));
+        assert_eq!(&c.get_string(), "fn a() {\n    x\n}");
+        assert_eq!(&c.typed_string(), "fn a(){\n    x}");
+
+        // short lines are padded with typed spaces
+        let cursor = c.materialize_display_pos((2, 3), true).unwrap();
+        assert_eq!(c.cursor_pos_at(cursor).0, (2, 3));
+        assert_eq!(&c.typed_string(), "fn a(){\n    x}  ");  // the newline in front of `}` is virtual
+        assert_eq!(&c.get_string(), "fn a() {\n    x\n}  ");
+        assert_eq!(c.materialize_display_pos((3, 0), true), None);
+    }
+
+    #[test]
+    fn test_block_replace() {
+        let mut c = Content::from_strings("let a=1;\nlet bb=22;", "let a  = 1;\nlet bb = 22;");
+        c.block_replace((0, 4), (1, 6), "x");
+        // the displayed space right of the rectangle is kept
+        assert_eq!(&c.typed_string(), "let x =1;\nlet x=22;");
+        c.for_each_cursor(|c| c.insert('y'));
+        assert_eq!(&c.typed_string(), "let xy =1;\nlet xy=22;");
+
+        // inserting into virtual whitespace
+        let mut c = Content::from_strings("fn a(){x}", "fn a() {\n    x\n}");
+        c.block_replace((1, 2), (2, 2), "/");
+        assert_eq!(&c.get_string(), "fn a() {\n  /  x\n} /");
+        assert_eq!(&c.typed_string(), "fn a(){\n  /  x} /");
+    }
+
+    #[test]
+    fn test_materialize_whitespace() {
+        let mut c = Content::from_strings("fn a(){x}", "fn a() {\n    x\n}");
+        c.materialize_whitespace(0, c.num_elmts());
+        assert_eq!(&c.typed_string(), "fn a() {\n    x\n}");
+        c.clear_virtual_whitespace();
+        assert_eq!(&c.get_string(), "fn a() {\n    x\n}");
+    }
+
+    #[test]
+    fn test_dematerialize_whitespace() {
+        let typed = "  fn a( x : u8 ) {\n    // a  comment\n    let s = \"a  b\" ;\n    x & &1 / / 2\n}\n";
+        let mut c = Content::from_string(typed);
+        c.set_cursor(c.end_position());
+        assert!(c.dematerialize_whitespace(0, c.num_elmts()));
+        assert_eq!(&c.typed_string(), "fn a(x:u8){// a  comment\nlet s=\"a  b\";x& &1/ /2}");
+        assert_eq!(c.cursor(), c.end_position());
+
+        // only within the range
+        let mut c = Content::from_string("a + b + c");
+        assert!(c.dematerialize_whitespace(0, 3));
+        assert_eq!(&c.typed_string(), "a+b + c");
+    }
+
+    #[test]
+    fn test_minimal_string() {
+        let visible = "fn test(x: &mut u8) -> u8 {\n    // one  two\n    let y = *x + 1;\n    return y;\n}\n";
+        let c = Content::from_strings("fn test(x:&mut u8)->u8{\n// one  two\nlet y=*x+1;return y;}", visible);
+        assert_eq!(c.get_string(), visible);
+        let minimal = c.to_minimal_string().unwrap();
+        assert_eq!(&minimal, "fn test(x:&mut u8)->u8{// one  two\nlet y=*x+1;return y;}");
+
+        let mut loaded = Content::from_string(&minimal);
+        assert!(loaded.update_virtual_whitespace(100).is_some());
+        assert_eq!(loaded.get_string(), visible);
+
+        // see `test`: the virtual whitespace splits `return1`
+        let typed = "fn test(&self,other:&mut usize){let x=(self+1)*other;return1<y}";
+        let visible = "fn test(&self, other: &mut usize) {\n    let x = (self + 1) * other;\n    return 1 < y\n}";
+        let c = Content::from_strings(&typed, &visible);
+        assert_eq!(c.check_virtual_whitespace(), Err("`return1` is displayed as `return`".to_string()));
+        assert!(c.to_minimal_string().is_err());
+    }
+
+    #[test]
+    fn test_deletion_merges_tokens() {
+        let mut c = Content::from_string("return 1 < y // a\nx");
+        c.set_cursor((6, 1));
+        assert!(c.deletion_merges_tokens(true));
+        c.backspace_keeping_tokens();
+        assert_eq!(&c.typed_string(), "return 1 < y // a\nx");
+        assert!(!c.deletion_merges_tokens(false));
+        c.set_cursor((6, 0));
+        assert!(c.deletion_merges_tokens(false));
+
+        // whitespace between `1` and `<` isn't needed
+        c.set_cursor((7, 1));
+        c.backspace_keeping_tokens();
+        assert_eq!(&c.typed_string(), "return 1< y // a\nx");
+
+        // the newline ends the comment
+        let end = c.num_elmts() - 2;
+        c.set_cursor((end, 1));
+        assert!(c.deletion_merges_tokens(true));
+        c.backspace();
+        assert_eq!(&c.typed_string(), "return 1< y // ax");
+
+        // word deletions keep whitespace that separates tokens as well
+        let mut c = Content::from_string("return 1+ 2 \"a b\"");
+        c.set_cursor((6, 1));
+        assert!(!c.delete_word_backward());
+        c.set_cursor((6, 0));
+        assert!(!c.delete_word_forward());
+        assert_eq!(&c.typed_string(), "return 1+ 2 \"a b\"");
+        assert!(!c.range_deletion_merges_tokens(c.position_before_char(7), c.position_before_char(9)));
+        assert!(!c.range_deletion_merges_tokens(c.position_before_char(10), c.position_before_char(12)));
+        assert!(c.range_deletion_merges_tokens((6, 0), c.position_before_char(8)));
+    }
+
+    #[test]
+    fn test_literals_are_opaque() {
+        let c = Content::from_string("let s = \"a  b\"; // c d\nr#\"e\nf\"#");
+        // only the whitespace between tokens is `Whitespace`
+        let typed: Vec<usize> = c.elmts.iter().map(|e| e.whitespace.typed.len()).filter(|n| *n > 0).collect();
+        assert_eq!(typed, vec!(1, 1, 1, 1, 1));
+        assert_eq!(&c.chars_of(0, c.num_elmts()), "lets=\"a  b\";// c dr#\"e\nf\"#");
+
+        // typing into a literal
+        let mut c = Content::from_string("x\"ab\"");
+        c.set_cursor((3, 0));
+        c.insert(' ');
+        assert_eq!(c.cursor(), (4, 0));
+        assert_eq!(&c.chars_of(0, c.num_elmts()), "x\"a b\"");
+        // and opening a comment in front of code
+        c.set_cursor((1, 0));
+        c.insert_str("//");
+        c.set_cursor((3, 0));
+        c.insert(' ');
+        assert_eq!(&c.chars_of(0, c.num_elmts()), "x// \"a b\"");
+        c.set_cursor((1, 0));
+        c.delete_range((1, 0), (3, 0));
+        assert_eq!(&c.chars_of(0, c.num_elmts()), "x\"a b\"");
+    }
+
+    #[test]
+    fn test_clear_virtual_whitespace_keeps_comments() {
+        let cases = [
+            ("fn a(){// b\nx}", "fn a() {\n    // b\n    x\n}"),
+            ("fn a(){/* b */x}", "fn a() {\n    /* b */\n    x\n}"),
+            // the formatter's newline can't end the comment, `x` is part of it in the typed text
+            ("fn a(){// b x\n}", "fn a() {\n    // b\n    x\n}"),
+            ("fn a(){let s=\"// b\";x}", "fn a() {\n    let s = \"// b\";\n    x\n}"),
+        ];
+        for (typed, visible) in cases.iter() {
+            let mut c = Content::from_strings(typed, visible);
+            let displayed = c.token_kinds();
+            assert!(c.check_virtual_whitespace().is_ok());
+            c.clear_virtual_whitespace();
+            assert_eq!(&c.get_string(), typed);
+            assert_eq!(c.token_kinds(), displayed);
+        }
+        let c = Content::from_strings("fn a(){// b x\n}", "fn a() {\n    // b\n    x\n}");
+        assert_eq!(&c.get_string(), "fn a() {\n    // b x\n}");
+    }
+
+    #[test]
+    fn test_update_virtual_whitespace_literals() {
+        // rustfmt with `wrap_comments`
+        let typed = "fn a(){/// one two three\nlet s=\"x  y\";b}";
+        let formatted = "fn a() {\n    /// one two\n    /// three\n    let s = \"x  y\";\n    b\n}";
+        let c = Content::from_strings(typed, formatted);
+        assert_eq!(&c.get_string(), "fn a() {\n    /// one two three\n    let s = \"x  y\";\n    b\n}");
+        assert!(c.check_virtual_whitespace().is_ok());
+
+        // a string split over lines by the formatter
+        let typed = "let s=\"aaa bbb\";x";
+        let formatted = "let s = \"aaa \\\n         bbb\";\nx";
+        let c = Content::from_strings(typed, formatted);
+        assert_eq!(&c.get_string(), "let s = \"aaa bbb\";\nx");
+    }
+
+    #[test]
+    fn test_whitespace_diff() {
+        let c = Content::from_strings("fn a( ){\nx ;}", "fn a() {\n    x;\n}");
+        assert_eq!(c.whitespace_diff(), vec!(
+            (WhitespaceDiff::Unchanged, "fn a(".to_string()),
+            (WhitespaceDiff::Overridden, " ".to_string()),
+            (WhitespaceDiff::Unchanged, ")".to_string()),
+            (WhitespaceDiff::Supplemented, " ".to_string()),
+            (WhitespaceDiff::Unchanged, "{\n".to_string()),
+            (WhitespaceDiff::Supplemented, "    ".to_string()),
+            (WhitespaceDiff::Unchanged, "x".to_string()),
+            (WhitespaceDiff::Overridden, " ".to_string()),
+            (WhitespaceDiff::Unchanged, ";".to_string()),
+            (WhitespaceDiff::Supplemented, "\n".to_string()),
+            (WhitespaceDiff::Unchanged, "}".to_string()),
+        ));
+        let displayed: String = c.whitespace_diff().into_iter().map(|(_, s)| s).collect();
+        assert_eq!(displayed, c.get_string());
+    }
+
+    #[test]
+    fn test_matching_items() {
+        let items = |s: &str| s.chars().map(|c| c.to_string()).collect::<Vec<_>>();
+        assert_eq!(matching_items(&items("abcd"), &items("axcde")), vec!((0, 0), (2, 2), (3, 3)));
+        assert_eq!(matching_items(&items("f(a,b)"), &items("f(a,b,)")), vec!((0, 0), (1, 1), (2, 2), (3, 3), (4, 4), (5, 6)));
+        assert_eq!(matching_items(&items("xaby"), &items("xbay")), vec!((0, 0), (2, 1), (3, 3)));
+        assert_eq!(matching_items(&items(""), &items("ab")), vec!());
+
+        // more changes than the diff looks for at once are diffed in several steps
+        let typed = "f(a)".repeat(3000);
+        let formatted = "f(a,)".repeat(3000);
+        let pairs = matching_items(&items(&typed), &items(&formatted));
+        assert_eq!(pairs.len(), typed.len());
+        assert!(pairs.iter().all(|&(i, j)| typed.as_bytes()[i] == formatted.as_bytes()[j]));
+        assert!(pairs.windows(2).all(|w| w[0].0 < w[1].0 && w[0].1 < w[1].1));
+    }
+
+    #[test]
+    fn test_restore_layout() {
+        let layout = "fn a(x:u8){\n  let y=x+1; // one\n  y}\n";
+        let mut c = Content::from_string("fn a(x: u8) {\n    let y = x + 1; // one\n    y\n}\n");
+        assert!(c.restore_layout(layout));
+        assert_eq!(&c.get_string(), layout);
+
+        // the changed tokens keep their whitespace
+        let mut c = Content::from_string("fn a(x: u8) {\n    let y = x * 2; // one\n    y\n}\n");
+        assert!(c.restore_layout(layout));
+        assert_eq!(&c.get_string(), "fn a(x:u8){\n  let y=x * 2; // one\n  y}\n");
+    }
+
+    #[test]
+    fn test_virtual_padding() {
+        let c = Content::from_strings("fn a(){\nlet s=\"x y\";}", "fn a() {\n    let s = \"x y\";\n}");
+        assert_eq!(c.virtual_padding(), vec!(
+            ((0, 6), 0, 1),
+            ((1, 0), 0, 4),
+            ((1, 5), 0, 1),
+            ((1, 6), 0, 1),
+            ((1, 12), 1, 0),
+        ));
+    }
+
+    #[test]
+    fn test_cursor_up_in_empty_document() {
+        let mut c = Content::from_string("");
+        c.cursor_up();
+        assert_eq!(c.cursor_pos(), ((0, 0), (0, 0)));
+    }
+
+    #[test]
+    fn test_cursor_home_on_empty_last_line() {
+        let mut c = Content::from_string("a\n");
+        c.cursor_down();
+        c.cursor_home();
+        assert_eq!(c.cursor_pos(), ((1, 0), (1, 0)));
+    }
+
+    // random edit sequences, checked against the typed text tracked alongside
+    #[derive(Clone, Debug)]
+    enum Op {
+        Insert(char),
+        Backspace,
+        Delete,
+        Left,
+        Right,
+        Up,
+        Down,
+        Home,
+        End,
+        Format(usize),  // window width
+        ClearVirtualWhitespace,
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        let chars = vec!['a', 'x', '1', ' ', '\n', '(', ')', '{', '}', ';', '=', '"', '\'', '/'];
+        prop_oneof![
+            4 => prop::sample::select(chars).prop_map(Op::Insert),
+            1 => Just(Op::Backspace),
+            1 => Just(Op::Delete),
+            1 => Just(Op::Left),
+            1 => Just(Op::Right),
+            1 => Just(Op::Up),
+            1 => Just(Op::Down),
+            1 => Just(Op::Home),
+            1 => Just(Op::End),
+            1 => (10usize..60).prop_map(Op::Format),
+            1 => Just(Op::ClearVirtualWhitespace),
+        ]
+    }
+
+    fn seed() -> impl Strategy<Value = &'static str> {
+        prop::sample::select(vec![
+            "",
+            "fn a(){}",
+            "fn a(x:u8)->u8{let s=\"a  b\";x+1}",
+            "// one  two\nstruct S{a:u8,\nb:char}",
+        ])
+    }
+
+    fn non_whitespace(s: &str) -> String {
+        s.chars().filter(|c| !c.is_whitespace()).collect()
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn edits_keep_invariants(seed in seed(), ops in prop::collection::vec(op(), 0..30)) {
+            let mut c = Content::from_string(seed);
+            let mut typed: Vec<char> = seed.chars().collect();
+            for op in ops {
+                let offset = c.typed_offset(c.cursor);
+                match op {
+                    Op::Insert(ch) => {
+                        c.insert(ch);
+                        typed.insert(offset, ch);
+                    },
+                    Op::Backspace => {
+                        c.backspace();
+                        if offset > 0 {
+                            typed.remove(offset - 1);
+                        }
+                    },
+                    Op::Delete => {
+                        c.delete();
+                        if offset < typed.len() {
+                            typed.remove(offset);
+                        }
+                    },
+                    Op::Left => c.cursor_left(),
+                    Op::Right => c.cursor_right(),
+                    Op::Up => c.cursor_up(),
+                    Op::Down => c.cursor_down(),
+                    Op::Home => c.cursor_home(),
+                    Op::End => c.cursor_end(),
+                    Op::Format(width) => {
+                        let before = non_whitespace(&c.get_string());
+                        c.update_virtual_whitespace(width);
+                        prop_assert_eq!(non_whitespace(&c.get_string()), before);
+                    },
+                    Op::ClearVirtualWhitespace => c.clear_virtual_whitespace(),
+                }
+
+                prop_assert!(c.cursor.0 < c.elmts.len());
+                prop_assert!(c.cursor.1 < c.elmts[c.cursor.0].whitespace.get_num_cursor_positions());
+                let mut cleared = c.clone();
+                cleared.clear_virtual_whitespace();
+                prop_assert_eq!(cleared.get_string(), typed.iter().collect::<String>());
+            }
+        }
+    }
 }
@@ -0,0 +1,408 @@
+// The editor state behind the web front-end, without anything that needs a browser: key
+// handling, commands, formatting and the derived state the view draws (highlighted text,
+// cursors, selections, search matches). `Model` forwards its messages here and renders the
+// fields, so the update logic can be tested natively.
+
+use crate::content::{Content, FormatStats};
+use crate::keymap::{Command, KeyChord, Keymap, Preset};
+use crate::lexer::TokenKind;
+use crate::search::Search;
+use crate::syntax::{self, StructuralSelection};
+use crate::vim::{Outcome, Vim};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FormatStatus {
+    Ok,
+    Error,
+    Pending,  // edited since the last formatting
+}
+
+pub struct Editor {
+    pub content: Content,
+    pub highlighted: Vec<(Option<TokenKind>, String)>,
+    pub line_numbers: Vec<Option<usize>>,
+    pub cursor2: ((usize, usize), (usize, usize)),
+    pub cursor_small: (usize, usize),
+    pub extra_cursors: Vec<(((usize, usize), (usize, usize)), (usize, usize))>,
+    pub auto_update: bool,
+    pub auto_close: bool,
+    pub window_width: usize,
+    pub format_status: FormatStatus,
+    pub format_stats: Option<FormatStats>,
+    pub keymap: Keymap,
+    pub vim: Vim,
+    pub structural_selection: StructuralSelection,
+    pub selection: Option<((usize, usize), (usize, usize))>,
+    pub block_selection: Option<((usize, usize), (usize, usize))>,  // display positions of two corners
+    pub bracket_match: Option<(((usize, usize), (usize, usize)), ((usize, usize), (usize, usize)))>,
+    pub search: Search,
+    pub replacement: String,
+    pub search_matches: Vec<((usize, usize), (usize, usize))>,  // display areas
+    pub search_error: Option<String>,
+    pub token_warning: Option<String>,  // set if the virtual whitespace hides joined tokens
+    messages: Vec<String>,  // for the console, see `take_messages`
+}
+
+impl Editor {
+    pub fn new(content: Content) -> Editor {
+        let mut editor = Editor {
+            content,
+            highlighted: vec!(),
+            line_numbers: vec!(),
+            cursor2: ((0, 0), (0, 0)),
+            cursor_small: (0, 0),
+            extra_cursors: vec!(),
+            auto_update: false,
+            auto_close: false,
+            window_width: 100,
+            format_status: FormatStatus::Pending,
+            format_stats: None,
+            keymap: Keymap::default(),
+            vim: Vim::default(),
+            structural_selection: StructuralSelection::default(),
+            selection: None,
+            block_selection: None,
+            bracket_match: None,
+            search: Search::default(),
+            replacement: "".to_string(),
+            search_matches: vec!(),
+            search_error: None,
+            token_warning: None,
+            messages: vec!(),
+        };
+        editor.refresh();
+        editor
+    }
+
+    // the displayed text, including virtual whitespace
+    pub fn text(&self) -> String {
+        self.highlighted.iter().map(|(_, s)| s.as_str()).collect()
+    }
+
+    fn log(&mut self, message: &str) {
+        self.messages.push(message.to_string());
+    }
+
+    // messages logged since the last call
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::replace(&mut self.messages, Vec::new())
+    }
+
+    pub fn update_cursor(&mut self) {
+        let (cursor2, cursor_small) = self.content.cursor_pos_2();
+        self.cursor2 = cursor2;
+        self.cursor_small = cursor_small;
+        self.extra_cursors = self.content.extra_cursor_positions();
+        let structural = self.structural_selection.current()
+            .map(|(start, end)| self.content.elmt_range_area(start, end));
+        self.selection = self.vim.selection(&self.content).map(|(start, end)| {
+            (self.content.cursor_pos_at(start).0, self.content.cursor_pos_at(end).0)
+        }).or(structural);
+        self.bracket_match = self.content.matching_bracket_at_cursor().map(|(a, b)| {
+            (self.content.elmt_range_area(a, a + 1), self.content.elmt_range_area(b, b + 1))
+        });
+    }
+
+    pub fn update_text(&mut self) {
+        self.highlighted = self.content.highlight();
+        self.line_numbers = self.content.line_numbers();
+        self.token_warning = self.content.check_virtual_whitespace().err();
+        self.update_search();
+    }
+
+    // after any change of the content or the cursor
+    pub fn refresh(&mut self) {
+        self.update_cursor();
+        self.update_text();
+    }
+
+    pub fn format(&mut self) {
+        match self.content.update_virtual_whitespace(self.window_width) {
+            Some(stats) => {
+                self.log(&stats.to_string());
+                self.format_status = FormatStatus::Ok;
+                self.format_stats = Some(stats);
+            },
+            None => {
+                self.log("error");
+                self.format_status = FormatStatus::Error;
+            }
+        }
+    }
+
+    fn content_edited(&mut self) {
+        if self.auto_update {
+            self.format();
+        } else {
+            self.format_status = FormatStatus::Pending;
+        }
+    }
+
+    pub fn set_width(&mut self, width: usize) {
+        self.window_width = width;
+        self.format();
+        self.refresh();
+    }
+
+    pub fn load(&mut self, s: &str) {
+        self.content = Content::from_string(s);
+        self.structural_selection.clear();
+        self.format();
+        self.refresh();
+    }
+
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+        self.vim = Vim::default();
+        self.update_cursor();
+    }
+
+    // returns whether the key did something, otherwise the browser should handle it
+    pub fn handle_key(&mut self, chord: &KeyChord) -> bool {
+        if self.keymap.preset() == Preset::Vim {
+            let outcome = self.vim.handle_key(chord, &mut self.content);
+            if outcome != Outcome::NotHandled {
+                self.structural_selection.clear();
+                if outcome == Outcome::Edited {
+                    self.content_edited();
+                }
+                self.refresh();
+                return true;
+            }
+        }
+        match self.keymap.command(chord) {
+            Some(cmd) => {
+                self.execute(cmd);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // the elmts whose whitespace is inside the selection, or all of them without one
+    fn whitespace_range(&self) -> (usize, usize) {
+        let visual = self.vim.selection(&self.content).map(|(start, end)| (start.0 + 1, end.0 + 1));
+        let structural = self.structural_selection.current().map(|(start, end)| (start + 1, end));
+        visual.or(structural).unwrap_or((0, self.content.num_elmts()))
+    }
+
+    // edits of a block selection always reformat, since they usually leave typed whitespace
+    // behind that has been virtual before
+    fn block_edited(&mut self) {
+        self.format();
+        self.refresh();
+    }
+
+    pub fn execute(&mut self, cmd: Command) {
+        let block = self.block_selection.take();
+        if let Some((from, to)) = block {
+            match cmd {
+                Command::Insert(c) if c != '\n' => {
+                    self.content.block_replace(from, to, &c.to_string());
+                    return self.block_edited();
+                },
+                Command::Backspace | Command::Delete | Command::DeleteWordBackward | Command::DeleteWordForward => {
+                    self.content.block_replace(from, to, "");
+                    // without columns in the selection, delete at every line's cursor instead
+                    if from.1 != to.1 {
+                        return self.block_edited();
+                    }
+                },
+                _ => (),
+            }
+        }
+        if cmd != Command::ExpandSelection && cmd != Command::ShrinkSelection {
+            self.structural_selection.clear();
+        }
+        match cmd {
+            Command::CursorLeft => self.content.for_each_cursor(Content::cursor_left),
+            Command::CursorRight => self.content.for_each_cursor(Content::cursor_right),
+            Command::CursorUp => self.content.for_each_cursor(Content::cursor_up),
+            Command::CursorDown => self.content.for_each_cursor(Content::cursor_down),
+            Command::CursorHome => self.content.for_each_cursor(Content::cursor_home),
+            Command::CursorEnd => self.content.for_each_cursor(Content::cursor_end),
+            Command::CursorWordLeft => self.content.for_each_cursor(Content::cursor_word_left),
+            Command::CursorWordRight => self.content.for_each_cursor(Content::cursor_word_right),
+            Command::Backspace => self.delete_keeping_tokens(Content::backspace_keeping_tokens),
+            Command::Delete => self.delete_keeping_tokens(Content::delete_keeping_tokens),
+            Command::DeleteWordBackward => self.delete_keeping_tokens(Content::delete_word_backward),
+            Command::DeleteWordForward => self.delete_keeping_tokens(Content::delete_word_forward),
+            Command::ExpandSelection => self.structural_selection.expand(&self.content),
+            Command::ShrinkSelection => self.structural_selection.shrink(),
+            Command::JumpToParent => syntax::jump_to_parent(&mut self.content),
+            Command::JumpToNextSibling => syntax::jump_to_sibling(&mut self.content, true),
+            Command::JumpToPrevSibling => syntax::jump_to_sibling(&mut self.content, false),
+            Command::JumpToMatchingBracket => self.content.jump_to_matching_bracket(),
+            Command::FindNext => self.find_next(true),
+            Command::FindPrev => self.find_next(false),
+            Command::AddCursorAtNextOccurrence => { self.content.add_cursor_at_next_occurrence(); },
+            Command::AddCursorAbove => { self.content.add_cursor_vertically(false); },
+            Command::AddCursorBelow => { self.content.add_cursor_vertically(true); },
+            Command::ClearExtraCursors => self.content.clear_extra_cursors(),
+            Command::BlockSelectUp | Command::BlockSelectDown | Command::BlockSelectLeft | Command::BlockSelectRight => {
+                let (from, (line, col)) = block.unwrap_or((self.cursor2.0, self.cursor2.0));
+                let to = match cmd {
+                    Command::BlockSelectUp => (line.saturating_sub(1), col),
+                    Command::BlockSelectDown => (line + 1, col),
+                    Command::BlockSelectLeft => (line, col.saturating_sub(1)),
+                    _ => (line, col + 1),
+                };
+                self.block_selection = Some((from, to));
+            },
+            Command::ToggleFold => self.content.toggle_fold(),
+            Command::FoldItems => syntax::fold_items(&mut self.content),
+            Command::UnfoldAll => self.content.unfold_all(),
+            Command::Insert(c) => {
+                let auto_close = self.auto_close;
+                self.content.for_each_cursor(|content| {
+                    if auto_close {
+                        content.insert_auto_close(c);
+                    } else {
+                        content.insert(c);
+                    }
+                });
+                self.content_edited();
+            },
+            Command::Format => self.format(),
+            Command::ClearVirtualWhitespace => {
+                self.content.clear_virtual_whitespace();
+                self.format_status = FormatStatus::Pending;
+            },
+            Command::MaterializeWhitespace => {
+                let (start, end) = self.whitespace_range();
+                self.content.materialize_whitespace(start, end);
+            },
+            Command::DematerializeWhitespace => {
+                let (start, end) = self.whitespace_range();
+                if self.content.dematerialize_whitespace(start, end) {
+                    self.format();
+                } else {
+                    self.log("whitespace can't be stripped without changing the tokens");
+                }
+            },
+            Command::ToggleAutoUpdate => self.auto_update = !self.auto_update,
+            Command::ToggleAutoClose => self.auto_close = !self.auto_close,
+        }
+        self.refresh();
+    }
+
+    // deletes with `delete` at every cursor, it returns false where it kept whitespace that
+    // separates tokens
+    fn delete_keeping_tokens(&mut self, delete: fn(&mut Content) -> bool) {
+        let mut kept = false;
+        self.content.for_each_cursor(|c| kept |= !delete(c));
+        if kept {
+            self.log("Keeping the whitespace, it separates two tokens");
+        }
+        self.content_edited();
+    }
+
+    pub fn update_search(&mut self) {
+        match self.search.find_all(&self.content) {
+            Ok(matches) => {
+                self.search_matches = matches.into_iter().map(|(start, end)| {
+                    (self.content.cursor_pos_at(start).0, self.content.cursor_pos_at(end).0)
+                }).collect();
+                self.search_error = None;
+            },
+            Err(e) => {
+                self.search_matches.clear();
+                self.search_error = Some(e);
+            },
+        }
+    }
+
+    fn find_next(&mut self, forward: bool) {
+        if let Ok(Some((start, _))) = self.search.find_next(&self.content, forward) {
+            self.content.set_cursor(start);
+        }
+    }
+
+    pub fn replace(&mut self, all: bool) {
+        let result = if all {
+            self.search.replace_all(&mut self.content, &self.replacement).map(|n| n > 0)
+        } else {
+            self.search.replace_next(&mut self.content, &self.replacement)
+        };
+        match result {
+            Ok(true) => self.content_edited(),
+            Ok(false) => (),
+            Err(e) => self.search_error = Some(e),
+        }
+        self.refresh();
+    }
+
+    // lines are counted from 0. Returns whether the line exists.
+    pub fn go_to_line(&mut self, line: usize, typed: bool) -> bool {
+        let found = if typed {
+            self.content.go_to_typed_line(line)
+        } else {
+            self.content.go_to_display_line(line)
+        };
+        self.update_cursor();
+        found
+    }
+
+    pub fn go_to_symbol(&mut self, start: usize) {
+        self.structural_selection.clear();
+        let cursor = self.content.position_before_char(start);
+        self.content.set_cursor(cursor);
+        self.update_cursor();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::run;
+
+    #[test]
+    fn test_typing() {
+        run("", r"
+            set auto_update on
+            type `fn a(){}`
+            expect text `fn a() {}\n`
+            expect typed `fn a(){}`
+            # the cursor spans the virtual newline at the end
+            expect cursor2 0:9 1:0
+            expect cursor_small 0:9
+            press ArrowLeft Enter
+            expect typed `fn a(){\n}`
+            expect text `fn a() {\n}\n`
+            expect cursor2 1:0 1:0
+        ");
+    }
+
+    #[test]
+    fn test_cursor_in_virtual_whitespace() {
+        // the cursor spans the virtual indentation, `cursor_small` is at the typed position
+        run("fn a(){1}", r"
+            press Ctrl+Shift+F
+            expect text `fn a() {\n    1\n}\n`
+            press ArrowRight ArrowRight ArrowRight ArrowRight ArrowRight ArrowRight ArrowRight
+            expect cursor2 0:8 1:4
+            expect cursor_small 0:8
+        ");
+    }
+
+    #[test]
+    fn test_without_auto_update() {
+        run("fn a(){}", r"
+            type `1`
+            expect typed `1fn a(){}`
+            expect text `1fn a(){}`
+            press Backspace
+            expect typed `fn a(){}`
+        ");
+    }
+
+    #[test]
+    fn test_kept_whitespace_is_logged_for_any_cursor() {
+        let mut editor = Editor::new(Content::from_string("a + b\nreturn 1"));
+        editor.content.set_cursor((1, 1));
+        editor.content.add_cursor((9, 1));
+        editor.execute(Command::Backspace);
+        assert_eq!(&editor.content.typed_string(), "a+ b\nreturn 1");
+        assert_eq!(editor.take_messages(), vec!("Keeping the whitespace, it separates two tokens"));
+    }
+}
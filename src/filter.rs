@@ -0,0 +1,119 @@
+// Git clean/smudge filter that keeps the repository in rustfmt's layout while every developer
+// keeps their own typed whitespace in the working copy.
+//
+// "clean" formats the working copy and stores the typed text in a sidecar directory, keyed by
+// a hash of the formatted text. "smudge" looks the formatted text up there. If the file changed
+// in between (e.g. after a pull), the last layout of that path is restored where the tokens
+// are unchanged, see `Content::restore_layout`.
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::content::{self, Content, GetString};
+
+// rustfmt's default
+pub const CANONICAL_WIDTH: usize = 100;
+
+// the text as it's committed. Text rustfmt can't parse is committed as it is.
+pub fn canonical(input: &str) -> String {
+    content::prettify_code(input.to_string(), CANONICAL_WIDTH).unwrap_or_else(|| input.to_string())
+}
+
+// the canonical text with the whitespace of `layout`, where its tokens are unchanged
+pub fn restore(canonical: &str, layout: &str) -> String {
+    let mut content = Content::from_string(canonical);
+    if content.restore_layout(layout) {
+        content.get_string()
+    } else {
+        canonical.to_string()
+    }
+}
+
+// FNV-1a, which unlike `DefaultHasher` is stable across Rust versions
+pub fn content_hash(s: &str) -> String {
+    let hash = s.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, b| (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3));
+    format!("{:016x}", hash)
+}
+
+pub struct Sidecar {
+    dir: PathBuf,
+}
+
+impl Sidecar {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Sidecar {
+        Sidecar { dir: dir.into() }
+    }
+
+    fn layout_file(&self, canonical: &str) -> PathBuf {
+        self.dir.join("layouts").join(content_hash(canonical))
+    }
+
+    fn path_file(&self, path: &str) -> PathBuf {
+        self.dir.join("paths").join(content_hash(path))
+    }
+
+    pub fn store(&self, path: &str, canonical: &str, layout: &str) -> io::Result<()> {
+        fs::create_dir_all(self.dir.join("layouts"))?;
+        fs::create_dir_all(self.dir.join("paths"))?;
+        fs::write(self.layout_file(canonical), layout)?;
+        fs::write(self.path_file(path), content_hash(canonical))
+    }
+
+    // the layout that was stored for exactly this canonical text
+    pub fn load(&self, canonical: &str) -> Option<String> {
+        fs::read_to_string(self.layout_file(canonical)).ok()
+    }
+
+    // the layout that was stored last for `path`
+    pub fn latest(&self, path: &str) -> Option<String> {
+        let hash = fs::read_to_string(self.path_file(path)).ok()?;
+        fs::read_to_string(self.dir.join("layouts").join(hash.trim())).ok()
+    }
+
+    pub fn clean(&self, path: &str, input: &str) -> io::Result<String> {
+        let canonical = canonical(input);
+        self.store(path, &canonical, input)?;
+        Ok(canonical)
+    }
+
+    pub fn smudge(&self, path: &str, canonical: &str) -> String {
+        match self.load(canonical) {
+            Some(layout) => layout,
+            None => match self.latest(path) {
+                Some(layout) => restore(canonical, &layout),
+                None => canonical.to_string(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_hash() {
+        assert_eq!(&content_hash(""), "cbf29ce484222325");
+        assert_eq!(&content_hash("a"), "af63dc4c8601ec8c");
+    }
+
+    #[test]
+    fn test_clean_smudge() {
+        let dir = std::env::temp_dir().join(format!("token_editor_filter_{}", std::process::id()));
+        let sidecar = Sidecar::new(&dir);
+        let typed = "fn a(x:u8)->u8{\n  x+1}\n";
+        let committed = sidecar.clean("src/a.rs", typed).unwrap();
+        assert_eq!(&committed, "fn a(x: u8) -> u8 {\n    x + 1\n}\n");
+        assert_eq!(&sidecar.smudge("src/a.rs", &committed), typed);
+
+        // changed upstream
+        let pulled = "fn a(x: u8) -> u8 {\n    x + 2\n}\n";
+        assert_eq!(&sidecar.smudge("src/a.rs", pulled), "fn a(x:u8)->u8{\n  x+ 2\n}\n");
+        assert_eq!(&sidecar.smudge("src/b.rs", pulled), pulled);
+
+        // not parseable, committed as it is
+        assert_eq!(&sidecar.clean("src/c.rs", "fn (").unwrap(), "fn (");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
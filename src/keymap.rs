@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Command {
+    CursorLeft,
+    CursorRight,
+    CursorUp,
+    CursorDown,
+    CursorHome,
+    CursorEnd,
+    CursorWordLeft,
+    CursorWordRight,
+    Backspace,
+    Delete,
+    DeleteWordBackward,
+    DeleteWordForward,
+    ExpandSelection,
+    ShrinkSelection,
+    JumpToParent,
+    JumpToNextSibling,
+    JumpToPrevSibling,
+    JumpToMatchingBracket,
+    FindNext,
+    FindPrev,
+    AddCursorAtNextOccurrence,
+    AddCursorAbove,
+    AddCursorBelow,
+    ClearExtraCursors,
+    BlockSelectUp,
+    BlockSelectDown,
+    BlockSelectLeft,
+    BlockSelectRight,
+    ToggleFold,
+    FoldItems,
+    UnfoldAll,
+    Insert(char),
+    Format,
+    ClearVirtualWhitespace,
+    MaterializeWhitespace,
+    DematerializeWhitespace,
+    ToggleAutoUpdate,
+    ToggleAutoClose,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Preset {
+    Default,
+    Emacs,
+    Vim,  // modal editing, see `vim::Vim`. The bindings apply to chords Vim doesn't handle.
+}
+
+// a key together with its modifiers. `key` uses the names of `KeyboardEvent.key`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct KeyChord {
+    pub key: String,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub meta: bool,
+}
+
+impl KeyChord {
+    pub fn new(key: &str, ctrl: bool, alt: bool, shift: bool, meta: bool) -> KeyChord {
+        let single_char = key.chars().count() == 1;
+        let has_modifier = ctrl || alt || meta;
+        KeyChord {
+            // for chords like Ctrl+Shift+A, the browser reports "A"
+            key: if single_char && has_modifier { key.to_lowercase() } else { key.to_string() },
+            ctrl,
+            alt,
+            // for printable keys, shift is already part of the key itself
+            shift: if single_char && !has_modifier { false } else { shift },
+            meta,
+        }
+    }
+
+    pub fn plain(key: &str) -> KeyChord {
+        KeyChord::new(key, false, false, false, false)
+    }
+
+    // the character typed by this chord, if it doesn't involve modifiers. AltGr is reported as
+    // Ctrl+Alt on Windows and macOS reports Option with Alt, so symbols typed with Alt count too,
+    // e.g. `{` on a German layout.
+    pub fn typed_char(&self) -> Option<char> {
+        let mut chars = self.key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if !self.ctrl && !self.alt && !self.meta => Some(c),
+            (Some(c), None) if self.alt && !self.meta && !c.is_alphanumeric() => Some(c),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = String;
+
+    // parses chords like "Ctrl+Shift+ArrowLeft", "Alt+x" or "Ctrl++"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (mods, key) = if s.ends_with("++") {
+            (&s[..s.len() - 2], "+")
+        } else {
+            match s.rfind('+') {
+                Some(i) => (&s[..i], &s[i + 1..]),
+                None => ("", s),
+            }
+        };
+        if key.is_empty() {
+            return Err(format!("Missing key in '{}'", s));
+        }
+        let (mut ctrl, mut alt, mut shift, mut meta) = (false, false, false, false);
+        for m in mods.split('+').filter(|m| !m.is_empty()) {
+            match m.to_lowercase().as_ref() {
+                "ctrl" | "control" | "c" => ctrl = true,
+                "alt" | "m" => alt = true,
+                "shift" | "s" => shift = true,
+                "meta" | "cmd" | "super" => meta = true,
+                other => return Err(format!("Unknown modifier '{}' in '{}'", other, s)),
+            }
+        }
+        // a printable key with only Shift is reported as the character it types, like "A" for
+        // Shift+a. Which one that is depends on the keyboard layout for anything but letters.
+        if shift && !ctrl && !alt && !meta && key.chars().count() == 1 {
+            if !key.chars().all(|c| c.is_ascii_alphabetic()) {
+                return Err(format!("Bind the character Shift types instead of '{}'", s));
+            }
+            return Ok(KeyChord::plain(&key.to_uppercase()));
+        }
+        Ok(KeyChord::new(key, ctrl, alt, shift, meta))
+    }
+}
+
+#[derive(Deserialize)]
+struct KeymapConfig {
+    preset: Option<Preset>,
+    #[serde(default)]
+    bindings: HashMap<String, Command>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    bindings: HashMap<KeyChord, Command>,
+    preset: Preset,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap::from_preset(Preset::Default)
+    }
+}
+
+impl Keymap {
+    pub fn from_preset(preset: Preset) -> Keymap {
+        use Command::*;
+        let mut keymap = Keymap { bindings: HashMap::new(), preset };
+        let defaults = [
+            ("ArrowLeft", CursorLeft),
+            ("ArrowRight", CursorRight),
+            ("ArrowUp", CursorUp),
+            ("ArrowDown", CursorDown),
+            ("Home", CursorHome),
+            ("End", CursorEnd),
+            ("Ctrl+ArrowLeft", CursorWordLeft),
+            ("Ctrl+ArrowRight", CursorWordRight),
+            ("Backspace", Backspace),
+            ("Delete", Delete),
+            ("Ctrl+Backspace", DeleteWordBackward),
+            ("Ctrl+Delete", DeleteWordForward),
+            ("Alt+Shift+ArrowRight", ExpandSelection),
+            ("Alt+Shift+ArrowLeft", ShrinkSelection),
+            ("Alt+ArrowUp", JumpToParent),
+            ("Alt+ArrowRight", JumpToNextSibling),
+            ("Alt+ArrowLeft", JumpToPrevSibling),
+            ("Ctrl+]", JumpToMatchingBracket),
+            ("F3", FindNext),
+            ("Shift+F3", FindPrev),
+            ("Ctrl+d", AddCursorAtNextOccurrence),
+            ("Ctrl+Alt+ArrowUp", AddCursorAbove),
+            ("Ctrl+Alt+ArrowDown", AddCursorBelow),
+            ("Escape", ClearExtraCursors),
+            ("Ctrl+Alt+Shift+ArrowUp", BlockSelectUp),
+            ("Ctrl+Alt+Shift+ArrowDown", BlockSelectDown),
+            ("Ctrl+Alt+Shift+ArrowLeft", BlockSelectLeft),
+            ("Ctrl+Alt+Shift+ArrowRight", BlockSelectRight),
+            ("Ctrl+Shift+{", ToggleFold),
+            ("Ctrl+Shift+}", UnfoldAll),
+            ("Enter", Insert('\n')),
+            ("Ctrl+Shift+f", Format),
+        ];
+        let emacs = [
+            ("Ctrl+b", CursorLeft),
+            ("Ctrl+f", CursorRight),
+            ("Ctrl+p", CursorUp),
+            ("Ctrl+n", CursorDown),
+            ("Ctrl+a", CursorHome),
+            ("Ctrl+e", CursorEnd),
+            ("Ctrl+h", Backspace),
+            ("Ctrl+d", Delete),
+            ("Alt+b", CursorWordLeft),
+            ("Alt+f", CursorWordRight),
+            ("Alt+Backspace", DeleteWordBackward),
+            ("Alt+d", DeleteWordForward),
+            ("Ctrl+m", Insert('\n')),
+            ("Alt+q", Format),
+        ];
+        let preset_bindings: &[(&str, Command)] = match preset {
+            Preset::Default | Preset::Vim => &[],
+            Preset::Emacs => &emacs,
+        };
+        for (chord, cmd) in defaults.iter().chain(preset_bindings) {
+            keymap.bind(chord.parse().unwrap(), *cmd);
+        }
+        keymap
+    }
+
+    pub fn from_json(s: &str) -> Result<Keymap, String> {
+        let config: KeymapConfig = serde_json::from_str(s).map_err(|e| e.to_string())?;
+        Keymap::from_config(config)
+    }
+
+    pub fn from_toml(s: &str) -> Result<Keymap, String> {
+        let config: KeymapConfig = toml::from_str(s).map_err(|e| e.to_string())?;
+        Keymap::from_config(config)
+    }
+
+    fn from_config(config: KeymapConfig) -> Result<Keymap, String> {
+        let mut keymap = Keymap::from_preset(config.preset.unwrap_or(Preset::Default));
+        for (chord, cmd) in config.bindings {
+            keymap.bind(chord.parse()?, cmd);
+        }
+        Ok(keymap)
+    }
+
+    pub fn preset(&self) -> Preset {
+        self.preset
+    }
+
+    pub fn bind(&mut self, chord: KeyChord, cmd: Command) {
+        self.bindings.insert(chord, cmd);
+    }
+
+    // bound command or, for plain printable keys, the insertion of that key
+    pub fn command(&self, chord: &KeyChord) -> Option<Command> {
+        self.bindings.get(chord).cloned()
+            .or_else(|| chord.typed_char().map(Command::Insert))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chord() {
+        let chord: KeyChord = "Ctrl+Shift+ArrowLeft".parse().unwrap();
+        assert_eq!(chord, KeyChord::new("ArrowLeft", true, false, true, false));
+        let chord: KeyChord = "Ctrl++".parse().unwrap();
+        assert_eq!(chord.key, "+");
+        assert!(chord.ctrl);
+        assert!("Hyper+x".parse::<KeyChord>().is_err());
+
+        // Shift+a is reported as "A"
+        let chord: KeyChord = "Shift+a".parse().unwrap();
+        assert_eq!(chord, KeyChord::new("A", false, false, true, false));
+        assert!("Shift+1".parse::<KeyChord>().is_err());
+        let chord: KeyChord = "Ctrl+Shift+a".parse().unwrap();
+        assert_eq!(chord, KeyChord::new("A", true, false, true, false));
+    }
+
+    #[test]
+    fn test_modifiers_dont_insert() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.command(&KeyChord::plain("A")), Some(Command::Insert('A')));
+        assert_eq!(keymap.command(&KeyChord::new("A", false, false, true, false)), Some(Command::Insert('A')));
+        assert_eq!(keymap.command(&KeyChord::new("a", true, false, false, false)), None);
+        assert_eq!(keymap.command(&KeyChord::new("a", false, true, false, false)), None);
+        // AltGr, or Option on macOS
+        assert_eq!(keymap.command(&KeyChord::new("{", true, true, false, false)), Some(Command::Insert('{')));
+        assert_eq!(keymap.command(&KeyChord::new("@", false, true, false, false)), Some(Command::Insert('@')));
+        assert_eq!(keymap.command(&KeyChord::new("{", true, false, false, false)), None);
+        assert_eq!(keymap.command(&KeyChord::plain("Enter")), Some(Command::Insert('\n')));
+        assert_eq!(keymap.command(&KeyChord::plain("F5")), None);
+    }
+
+    #[test]
+    fn test_emacs_preset() {
+        let keymap = Keymap::from_preset(Preset::Emacs);
+        assert_eq!(keymap.command(&KeyChord::new("f", true, false, false, false)), Some(Command::CursorRight));
+        assert_eq!(keymap.command(&KeyChord::plain("ArrowRight")), Some(Command::CursorRight));
+    }
+
+    #[test]
+    fn test_load_config() {
+        let keymap = Keymap::from_toml(r#"
+            preset = "Emacs"
+            [bindings]
+            "Ctrl+f" = "Format"
+            "Tab" = { Insert = " " }
+        "#).unwrap();
+        assert_eq!(keymap.command(&KeyChord::new("f", true, false, false, false)), Some(Command::Format));
+        assert_eq!(keymap.command(&KeyChord::new("b", true, false, false, false)), Some(Command::CursorLeft));
+        assert_eq!(keymap.command(&KeyChord::plain("Tab")), Some(Command::Insert(' ')));
+
+        let keymap = Keymap::from_json(r#"{"bindings": {"Alt+ArrowUp": "CursorHome"}}"#).unwrap();
+        assert_eq!(keymap.command(&KeyChord::new("ArrowUp", false, true, false, false)), Some(Command::CursorHome));
+        assert!(Keymap::from_json(r#"{"bindings": {"Foo+x": "Format"}}"#).is_err());
+    }
+}
@@ -0,0 +1,299 @@
+// A small, forgiving lexer for Rust source.
+//
+// It never fails: anything it doesn't understand becomes a `Punct` token.
+// Token positions are char indices into the lexed input.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    Ident,
+    Lifetime,
+    Macro,
+    Number,
+    Str,
+    Char,
+    Comment,
+    Punct,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+    "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait", "true",
+    "type", "unsafe", "use", "where", "while",
+];
+
+// longest first, so that `<<=` wins over `<<` and `<`
+const MULTI_CHAR_PUNCT: &[&str] = &[
+    "<<=", ">>=", "...", "..=", "::", "->", "=>", "==", "!=", "<=", ">=", "&&", "||", "+=",
+    "-=", "*=", "/=", "%=", "^=", "&=", "|=", "<<", ">>", "..",
+];
+
+impl TokenKind {
+    pub fn css_class(&self) -> &'static str {
+        match self {
+            TokenKind::Keyword => "tok-keyword",
+            TokenKind::Ident => "tok-ident",
+            TokenKind::Lifetime => "tok-lifetime",
+            TokenKind::Macro => "tok-macro",
+            TokenKind::Number => "tok-number",
+            TokenKind::Str => "tok-string",
+            TokenKind::Char => "tok-char",
+            TokenKind::Comment => "tok-comment",
+            TokenKind::Punct => "tok-punct",
+        }
+    }
+    // comments and literals, including raw strings. Whitespace inside them is part of the token.
+    pub fn is_opaque(&self) -> bool {
+        match self {
+            TokenKind::Str | TokenKind::Char | TokenKind::Comment => true,
+            _ => false,
+        }
+    }
+}
+
+pub fn is_keyword(s: &str) -> bool {
+    KEYWORDS.contains(&s)
+}
+
+pub fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+pub fn is_ident_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+pub fn tokenize(chars: &[char]) -> Vec<Token> {
+    let mut tokens = vec!();
+    let mut pos = 0;
+    while pos < chars.len() {
+        let c = chars[pos];
+        if c.is_whitespace() {
+            pos += 1;
+            continue;
+        }
+        let (kind, end) = lex_token(chars, pos);
+        tokens.push(Token { kind, start: pos, end });
+        pos = end;
+    }
+    tokens
+}
+
+pub fn tokenize_str(s: &str) -> Vec<Token> {
+    let chars: Vec<char> = s.chars().collect();
+    tokenize(&chars)
+}
+
+fn lex_token(chars: &[char], start: usize) -> (TokenKind, usize) {
+    let at = |i: usize| chars.get(i).cloned();
+    let c = chars[start];
+
+    match (c, at(start + 1)) {
+        ('/', Some('/')) => {
+            let mut end = start;
+            while end < chars.len() && chars[end] != '\n' {
+                end += 1;
+            }
+            return (TokenKind::Comment, end);
+        },
+        ('/', Some('*')) => return (TokenKind::Comment, lex_block_comment(chars, start)),
+        ('"', _) => return (TokenKind::Str, lex_quoted(chars, start + 1, '"')),
+        ('\'', _) => return lex_quote(chars, start),
+        ('r', Some('"')) | ('r', Some('#')) => {
+            if let Some(end) = lex_raw_string(chars, start + 1) {
+                return (TokenKind::Str, end);
+            }
+        },
+        ('b', Some('"')) => return (TokenKind::Str, lex_quoted(chars, start + 2, '"')),
+        ('b', Some('\'')) => return (TokenKind::Char, lex_quoted(chars, start + 2, '\'')),
+        ('b', Some('r')) => {
+            if let Some(end) = lex_raw_string(chars, start + 2) {
+                return (TokenKind::Str, end);
+            }
+        },
+        _ => (),
+    }
+
+    if c.is_ascii_digit() {
+        return (TokenKind::Number, lex_number(chars, start));
+    }
+
+    if is_ident_start(c) {
+        let mut end = start + 1;
+        // raw identifiers like `r#type`
+        if c == 'r' && at(end) == Some('#') && at(end + 1).map_or(false, is_ident_start) {
+            end += 1;
+        }
+        while end < chars.len() && is_ident_continue(chars[end]) {
+            end += 1;
+        }
+        let word: String = chars[start..end].iter().collect();
+        if at(end) == Some('!') && at(end + 1) != Some('=') {
+            return (TokenKind::Macro, end + 1);
+        }
+        let kind = if is_keyword(&word) { TokenKind::Keyword } else { TokenKind::Ident };
+        return (kind, end);
+    }
+
+    for p in MULTI_CHAR_PUNCT {
+        let len = p.chars().count();
+        if start + len <= chars.len() && chars[start..start + len].iter().cloned().eq(p.chars()) {
+            return (TokenKind::Punct, start + len);
+        }
+    }
+    (TokenKind::Punct, start + 1)
+}
+
+// `pos` points behind the opening delimiter
+fn lex_quoted(chars: &[char], mut pos: usize, delimiter: char) -> usize {
+    while pos < chars.len() {
+        match chars[pos] {
+            '\\' => pos += 2,
+            x if x == delimiter => return pos + 1,
+            _ => pos += 1,
+        }
+    }
+    chars.len()
+}
+
+// `pos` points behind the `r`
+fn lex_raw_string(chars: &[char], mut pos: usize) -> Option<usize> {
+    let mut hashes = 0;
+    while chars.get(pos) == Some(&'#') {
+        hashes += 1;
+        pos += 1;
+    }
+    if chars.get(pos) != Some(&'"') {
+        return None;
+    }
+    pos += 1;
+    while pos < chars.len() {
+        if chars[pos] == '"' && (1..=hashes).all(|i| chars.get(pos + i) == Some(&'#')) {
+            return Some(pos + 1 + hashes);
+        }
+        pos += 1;
+    }
+    Some(chars.len())
+}
+
+fn lex_block_comment(chars: &[char], start: usize) -> usize {
+    let mut depth = 0;
+    let mut pos = start;
+    while pos < chars.len() {
+        match (chars[pos], chars.get(pos + 1)) {
+            ('/', Some('*')) => { depth += 1; pos += 2; },
+            ('*', Some('/')) => {
+                depth -= 1;
+                pos += 2;
+                if depth == 0 {
+                    return pos;
+                }
+            },
+            _ => pos += 1,
+        }
+    }
+    chars.len()
+}
+
+// decides between char literals and lifetimes
+fn lex_quote(chars: &[char], start: usize) -> (TokenKind, usize) {
+    match (chars.get(start + 1), chars.get(start + 2)) {
+        (Some('\\'), _) => (TokenKind::Char, lex_quoted(chars, start + 1, '\'')),
+        (Some(_), Some('\'')) => (TokenKind::Char, start + 3),
+        (Some(c), _) if is_ident_start(*c) => {
+            let mut end = start + 2;
+            while end < chars.len() && is_ident_continue(chars[end]) {
+                end += 1;
+            }
+            (TokenKind::Lifetime, end)
+        },
+        _ => (TokenKind::Punct, start + 1),
+    }
+}
+
+fn lex_number(chars: &[char], start: usize) -> usize {
+    let mut end = start;
+    while end < chars.len() {
+        let c = chars[end];
+        let next_is_digit = chars.get(end + 1).map_or(false, |x| x.is_ascii_digit());
+        if is_ident_continue(c) || (c == '.' && next_is_digit) {
+            end += 1;
+        } else {
+            break;
+        }
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(s: &str) -> Vec<(TokenKind, String)> {
+        let chars: Vec<char> = s.chars().collect();
+        tokenize(&chars).into_iter()
+            .map(|t| (t.kind, chars[t.start..t.end].iter().collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        use TokenKind::*;
+        assert_eq!(kinds("fn test(x: &'a u8) { println!(\"a b\"); }"), vec!(
+            (Keyword, "fn".to_string()),
+            (Ident, "test".to_string()),
+            (Punct, "(".to_string()),
+            (Ident, "x".to_string()),
+            (Punct, ":".to_string()),
+            (Punct, "&".to_string()),
+            (Lifetime, "'a".to_string()),
+            (Ident, "u8".to_string()),
+            (Punct, ")".to_string()),
+            (Punct, "{".to_string()),
+            (Macro, "println!".to_string()),
+            (Punct, "(".to_string()),
+            (Str, "\"a b\"".to_string()),
+            (Punct, ")".to_string()),
+            (Punct, ";".to_string()),
+            (Punct, "}".to_string()),
+        ));
+    }
+
+    #[test]
+    fn test_tokenize_literals_and_comments() {
+        use TokenKind::*;
+        assert_eq!(kinds("'x' '\\n' 1.5e3 0..2 // a b\nr#\"q\"#"), vec!(
+            (Char, "'x'".to_string()),
+            (Char, "'\\n'".to_string()),
+            (Number, "1.5e3".to_string()),
+            (Number, "0".to_string()),
+            (Punct, "..".to_string()),
+            (Number, "2".to_string()),
+            (Comment, "// a b".to_string()),
+            (Str, "r#\"q\"#".to_string()),
+        ));
+        assert_eq!(kinds("/* a /* b */ c */x"), vec!(
+            (Comment, "/* a /* b */ c */".to_string()),
+            (Ident, "x".to_string()),
+        ));
+    }
+
+    #[test]
+    fn test_tokenize_operators() {
+        use TokenKind::*;
+        assert_eq!(kinds("a!=b"), vec!(
+            (Ident, "a".to_string()),
+            (Punct, "!=".to_string()),
+            (Ident, "b".to_string()),
+        ));
+        assert_eq!(kinds("x<<=1").len(), 3);
+    }
+}
@@ -3,37 +3,197 @@ use yew::services::ConsoleService;
 use stdweb::web::event::KeyDownEvent;
 use stdweb::web::event::IKeyboardEvent;
 use stdweb::web::event::IEvent;
+use stdweb::web::event::IMouseEvent;
+use stdweb::web::event::{ClickEvent, MouseDownEvent, MouseMoveEvent};
 
-mod content;
-use content::{Content, GetString};
+pub mod content;
+pub mod editor;
+pub mod filter;
+pub mod keymap;
+pub mod lexer;
+pub mod lsp;
+#[cfg(test)]
+mod scenario;
+mod search;
+mod status_bar;
+mod syntax;
+pub mod tui;
+mod vim;
+use content::{Content, WhitespaceDiff};
+use editor::Editor;
+use keymap::{Command, KeyChord, Keymap, Preset};
+use status_bar::StatusBar;
+use syntax::Symbol;
+use vim::Mode;
+use lexer::TokenKind;
 
 const TEXT_SIZE: usize = 12;
 
 pub struct Model {
     console: ConsoleService,
-    text: String,
-    //cursor: CursorPos,
-    cursor2: ((usize, usize), (usize, usize)),
-    cursor_small: (usize, usize),
-    content: Content,
-    auto_update: bool,
-    window_width: usize,
-    char_dimensions: (f32, f32)
+    editor: Editor,
+    char_dimensions: (f32, f32),
+    dark_theme: bool,
+    show_typed_lines: bool,
+    drag: Option<((usize, usize), bool)>,  // start of an Alt+drag and whether the mouse moved
+    go_to_line: String,
+    go_to_typed_line: bool,
+    symbol_filter: String,
+    saved: String,  // the content with minimal typed whitespace, or why it can't be saved
 }
 
 pub enum Msg {
     KeyEvt(KeyDownEvent),
+    Click(ClickEvent),
+    MouseDown(MouseDownEvent),
+    MouseMove(MouseMoveEvent),
     ClearVirtualWhitespace,
+    MaterializeWhitespace,
+    DematerializeWhitespace,
     Format,
+    FoldItems,
+    UnfoldAll,
     ToggleAutoUpdate,
+    ToggleAutoClose,
+    ToggleTheme,
+    ToggleTypedLines,
+    SetSearchQuery(String),
+    ToggleCaseSensitive,
+    ToggleWholeWord,
+    ToggleRegex,
+    ToggleIgnoreWhitespace,
+    SetReplacement(String),
+    ReplaceNext,
+    ReplaceAll,
+    SetGoToLine(String),
+    ToggleGoToTypedLine,
+    GoToLine,
+    SetSymbolFilter(String),
+    GoToSymbol(usize),
+    SetKeymapPreset(Preset),
+    LoadKeymapConfig(String),
+    Save,
+    SetSaved(String),
+    Load,
     UpdateWidth(usize)
 }
 
 impl Model {
-    fn update_cursor(&mut self) {
-        let (cursor2, cursor_small) = self.content.cursor_pos_2();
-        self.cursor2 = cursor2;
-        self.cursor_small = cursor_small;
+    // display position (line, column) of a mouse event
+    fn display_pos(&self, client_x: i32, client_y: i32) -> Option<(usize, usize)> {
+        use stdweb::web::IElement;
+        let rect = stdweb::web::document().get_element_by_id("code")?.get_bounding_client_rect();
+        let (w, h) = self.char_dimensions;
+        let x = (client_x as f64 - rect.get_left()) / w as f64;
+        let y = (client_y as f64 - rect.get_top()) / h as f64;
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+        Some((y as usize, x.round() as usize))
+    }
+
+    // styles of the three divs (first line, lines in between, last line) covering an area
+    fn area_styles(&self, area: ((usize, usize), (usize, usize))) -> (String, String, String) {
+        let (w, h) = self.char_dimensions;
+        let (start, end) = area;
+        let width_first_line = w * if start.0 == end.0 {
+            end.1 - start.1
+        } else {
+            self.editor.window_width.saturating_sub(start.1)
+        } as f32;
+        let first_line_style = format!("top: {}px; left: {}px; width: {}px; height: {}px;", h*start.0 as f32, w*start.1 as f32, width_first_line, h);
+        let num_mid_lines = (end.0 - start.0).checked_sub(1).unwrap_or(0);
+        let mid_lines_style = format!("top: {}px; left: 0px; width: {}px; height: {}px;", h*(start.0 + 1) as f32, w * self.editor.window_width as f32, h*num_mid_lines as f32);
+        let last_line_width = if start.0 == end.0 {
+            0
+        } else {
+            end.1
+        };
+        let last_line_style = format!("top: {}px; left: 0px; width: {}px; height: {}px;", h*end.0 as f32, w * last_line_width as f32, h);
+        (first_line_style, mid_lines_style, last_line_style)
+    }
+
+    fn view_area(&self, area: ((usize, usize), (usize, usize)), class: &str) -> Html<Model> {
+        let (first_line_style, mid_lines_style, last_line_style) = self.area_styles(area);
+        html! {
+            <div>
+                <div class=class, style=first_line_style, ></div>
+                <div class=class, style=mid_lines_style, ></div>
+                <div class=class, style=last_line_style, ></div>
+            </div>
+        }
+    }
+
+    fn view_symbols(&self) -> Html<Model> {
+        let filter = self.symbol_filter.to_lowercase();
+        let symbols: Vec<Symbol> = syntax::symbols(&self.editor.content).into_iter()
+            .filter(|s| s.name.to_lowercase().contains(&filter))
+            .collect();
+        html! {
+            <ul class="symbols", >
+                { for symbols.iter().map(view_symbol) }
+            </ul>
+        }
+    }
+
+    // an additional cursor, drawn like the main one
+    fn view_extra_cursor(&self, (cursor2, cursor_small): &(((usize, usize), (usize, usize)), (usize, usize))) -> Html<Model> {
+        let (w, h) = self.char_dimensions;
+        let caret = |pos: (usize, usize), visible: bool| format!(
+            "top: {}px; left: {}px; height: {}px; display: {};",
+            h * pos.0 as f32, w * pos.1 as f32 - 1.0, h, if visible { "block" } else { "none" },
+        );
+        let caret_style = caret(cursor2.0, cursor2.0 == cursor2.1);
+        let small_style = caret(*cursor_small, (cursor2.0).0 != (cursor2.1).0);
+        let area = if cursor2.0 == cursor2.1 { Default::default() } else { self.area_styles(*cursor2) };
+        html! {
+            <div>
+                <div class="extra-cursor", style=caret_style, ></div>
+                <div class="area", style=area.0, ></div>
+                <div class="area", style=area.1, ></div>
+                <div class="area", style=area.2, ></div>
+                <div class="extra-cursor", style=small_style, ></div>
+            </div>
+        }
+    }
+
+    fn view_block_selection(&self) -> Html<Model> {
+        let areas: Vec<_> = match self.editor.block_selection {
+            Some((from, to)) => {
+                let (left, right) = (std::cmp::min(from.1, to.1), std::cmp::max(from.1, to.1));
+                (std::cmp::min(from.0, to.0)..=std::cmp::max(from.0, to.0))
+                    .map(|line| ((line, left), (line, right)))
+                    .collect()
+            },
+            None => vec!(),
+        };
+        html! {
+            <div>
+                { for areas.into_iter().map(|area| self.view_area(area, "area block-selection")) }
+            </div>
+        }
+    }
+
+    fn mode_text(&self) -> String {
+        if self.editor.keymap.preset() != Preset::Vim {
+            return "".to_string();
+        }
+        match self.editor.vim.mode() {
+            Mode::Normal => format!("NORMAL {}", self.editor.vim.pending_keys()),
+            Mode::Insert => "-- INSERT --".to_string(),
+            Mode::Visual => "-- VISUAL --".to_string(),
+        }
+    }
+
+    fn gutter_text(&self) -> String {
+        self.editor.line_numbers.iter().enumerate().map(|(i, typed)| {
+            if self.show_typed_lines {
+                let typed = typed.map(|x| (x + 1).to_string()).unwrap_or_default();
+                format!("{:>4} {:>4}\n", i + 1, typed)
+            } else {
+                format!("{:>4}\n", i + 1)
+            }
+        }).collect()
     }
 }
 
@@ -65,110 +225,156 @@ impl Component for Model {
 
         Model {
             console: console,
-            text: content.get_string(),
-            //cursor: content.cursor_pos(),
-            cursor2: ((0, 11), (2, 4)),
-            cursor_small: (0, 0),
-            content,
-            auto_update: false,
-            window_width: 100,
-            char_dimensions: (rect.get_width() as f32, rect.get_height() as f32)
+            editor: Editor::new(content),
+            char_dimensions: (rect.get_width() as f32, rect.get_height() as f32),
+            dark_theme: false,
+            show_typed_lines: false,
+            drag: None,
+            go_to_line: "".to_string(),
+            go_to_typed_line: false,
+            symbol_filter: "".to_string(),
+            saved: "".to_string(),
         }
     }
 
     fn update(&mut self, msg: Self::Message) -> ShouldRender {
+        let render = self.handle(msg);
+        for message in self.editor.take_messages() {
+            self.console.log(&message);
+        }
+        render
+    }
+}
+
+impl Model {
+    fn handle(&mut self, msg: Msg) -> ShouldRender {
         match msg {
             Msg::KeyEvt(e) => {
+                let chord = KeyChord::new(&e.key(), e.ctrl_key(), e.alt_key(), e.shift_key(), e.meta_key());
+                if !self.editor.handle_key(&chord) {
+                    return false;
+                }
                 e.stop_propagation();
                 e.prevent_default();
-                match e.key().as_ref() {
-                    "ArrowLeft" => {
-                        self.content.cursor_left();
-                        self.update_cursor();
-                    },
-                    "ArrowRight" => {
-                        self.content.cursor_right();
-                        self.update_cursor();
-                    },
-                    "ArrowDown" => {
-                        self.content.cursor_down();
-                        self.update_cursor();
-                    },
-                    "ArrowUp" => {
-                        self.content.cursor_up();
-                        self.update_cursor();
-                    },
-                    "End" => {
-                        self.content.cursor_end();
-                        self.update_cursor();
-                    },
-                    "Home" => {
-                        self.content.cursor_home();
-                        self.update_cursor();
-                    },
-                    "Backspace" => {
-                        self.content.backspace();
-                        if self.auto_update {
-                            let res = self.content.update_virtual_whitespace(self.window_width);
-                            self.console.log(&res);
-                        }
-                        self.update_cursor();
-                        self.text = self.content.get_string();
-                    },
-                    "Delete" => {
-                        self.content.delete();
-                        if self.auto_update {
-                            let res = self.content.update_virtual_whitespace(self.window_width);
-                            self.console.log(&res);
-                        }
-                        self.update_cursor();
-                        self.text = self.content.get_string();
-                    },
-                    "Enter" => {
-                        self.content.insert('\n');
-                        if self.auto_update {
-                            let res = self.content.update_virtual_whitespace(self.window_width);
-                            self.console.log(&res);
-                        }
-                        self.update_cursor();
-                        self.text = self.content.get_string();
+            },
+            Msg::Click(e) => {
+                // the end of an Alt+drag
+                if let Some((_, true)) = self.drag.take() {
+                    return true;
+                }
+                let pos = match self.display_pos(e.client_x(), e.client_y()) {
+                    Some(pos) => pos,
+                    None => return false,
+                };
+                let editor = &mut self.editor;
+                editor.structural_selection.clear();
+                editor.block_selection = None;
+                if e.alt_key() && e.shift_key() {
+                    editor.content.select_column(pos);
+                } else if let Some(cursor) = editor.content.cursor_for_display_pos(pos) {
+                    if e.alt_key() {
+                        editor.content.add_cursor(cursor);
+                    } else {
+                        editor.content.clear_extra_cursors();
+                        editor.content.set_cursor(cursor);
+                    }
+                }
+                editor.update_cursor();
+            },
+            Msg::MouseDown(e) => {
+                self.drag = match self.display_pos(e.client_x(), e.client_y()) {
+                    Some(pos) if e.alt_key() => Some((pos, false)),
+                    _ => None,
+                };
+                return false;
+            },
+            Msg::MouseMove(e) => {
+                let (start, _) = match self.drag {
+                    Some(drag) => drag,
+                    None => return false,
+                };
+                match self.display_pos(e.client_x(), e.client_y()) {
+                    Some(pos) if pos != start => {
+                        self.drag = Some((start, true));
+                        self.editor.block_selection = Some((start, pos));
                     },
-                    x if x.len() == 1 => {
-                        self.content.insert(x.chars().next().unwrap());
-                        if self.auto_update {
-                            let res = self.content.update_virtual_whitespace(self.window_width);
-                            self.console.log(&res);
+                    _ => return false,
+                }
+            },
+            Msg::ClearVirtualWhitespace => self.editor.execute(Command::ClearVirtualWhitespace),
+            Msg::MaterializeWhitespace => self.editor.execute(Command::MaterializeWhitespace),
+            Msg::DematerializeWhitespace => self.editor.execute(Command::DematerializeWhitespace),
+            Msg::Format => self.editor.execute(Command::Format),
+            Msg::FoldItems => self.editor.execute(Command::FoldItems),
+            Msg::UnfoldAll => self.editor.execute(Command::UnfoldAll),
+            Msg::SetSearchQuery(query) => {
+                self.editor.search.query = query;
+                self.editor.update_search();
+            },
+            Msg::ToggleCaseSensitive => {
+                self.editor.search.options.case_sensitive = !self.editor.search.options.case_sensitive;
+                self.editor.update_search();
+            },
+            Msg::ToggleWholeWord => {
+                self.editor.search.options.whole_word = !self.editor.search.options.whole_word;
+                self.editor.update_search();
+            },
+            Msg::ToggleRegex => {
+                self.editor.search.options.regex = !self.editor.search.options.regex;
+                self.editor.update_search();
+            },
+            Msg::ToggleIgnoreWhitespace => {
+                self.editor.search.options.ignore_whitespace = !self.editor.search.options.ignore_whitespace;
+                self.editor.update_search();
+            },
+            Msg::SetReplacement(s) => self.editor.replacement = s,
+            Msg::ReplaceNext => self.editor.replace(false),
+            Msg::ReplaceAll => self.editor.replace(true),
+            Msg::SetGoToLine(s) => self.go_to_line = s,
+            Msg::ToggleGoToTypedLine => self.go_to_typed_line = !self.go_to_typed_line,
+            Msg::GoToLine => {
+                // lines are entered 1-based
+                match self.go_to_line.trim().parse::<usize>() {
+                    Ok(line) if line > 0 => {
+                        if !self.editor.go_to_line(line - 1, self.go_to_typed_line) {
+                            self.console.log(&format!("No line {}", line));
                         }
-                        self.update_cursor();
-                        self.text = self.content.get_string();
                     },
-                    _ => ()
+                    _ => self.console.log(&format!("Invalid line '{}'", self.go_to_line)),
                 }
-                self.console.log(&format!("{:?}", e.key()));
-                // FIXME: implement
-                
-            },
-            Msg::ClearVirtualWhitespace => {
-                self.content.clear_virtual_whitespace();
-                self.update_cursor();
-                self.text = self.content.get_string();
-            },
-            Msg::Format => {
-                let res = self.content.update_virtual_whitespace(self.window_width);
-                self.console.log(&res);
-                self.update_cursor();
-                self.text = self.content.get_string();
-            },
-            Msg::ToggleAutoUpdate => {
-                self.auto_update = !self.auto_update;
-            },
-            Msg::UpdateWidth(n) => {
-                self.window_width = n;
-                let res = self.content.update_virtual_whitespace(self.window_width);
-                self.console.log(&res);
-                self.update_cursor();
-                self.text = self.content.get_string();
-            }
+            },
+            Msg::SetSymbolFilter(s) => self.symbol_filter = s,
+            Msg::GoToSymbol(start) => self.editor.go_to_symbol(start),
+            Msg::SetKeymapPreset(preset) => self.editor.set_keymap(Keymap::from_preset(preset)),
+            Msg::LoadKeymapConfig(config) => {
+                // the config may be written in TOML or JSON
+                match Keymap::from_toml(&config).or_else(|_| Keymap::from_json(&config)) {
+                    Ok(keymap) => self.editor.set_keymap(keymap),
+                    Err(e) => self.console.log(&format!("Invalid keymap config: {}", e)),
+                }
+            },
+            Msg::Save => {
+                self.saved = match self.editor.content.to_minimal_string() {
+                    Ok(s) => s,
+                    Err(e) => format!("Can't save: {}", e),
+                };
+            },
+            Msg::SetSaved(s) => self.saved = s,
+            // saved files only have the whitespace that separates tokens, the formatter
+            // restores the layout
+            Msg::Load => {
+                let saved = self.saved.clone();
+                self.editor.load(&saved);
+            },
+            Msg::ToggleAutoUpdate => self.editor.execute(Command::ToggleAutoUpdate),
+            Msg::ToggleAutoClose => self.editor.execute(Command::ToggleAutoClose),
+            Msg::ToggleTheme => {
+                self.dark_theme = !self.dark_theme;
+            },
+            Msg::ToggleTypedLines => {
+                self.show_typed_lines = !self.show_typed_lines;
+            },
+            Msg::UpdateWidth(n) => self.editor.set_width(n),
         }
         true
     }
@@ -178,48 +384,161 @@ impl Renderable<Model> for Model {
     fn view(&self) -> Html<Self> {
         let (w, h) = self.char_dimensions;
 
-        let x = (self.cursor2.0).1 as f32 * w;
-        let y = (self.cursor2.0).0 as f32 * h;
-        let s = format!("background-color: #7799bb; position: absolute; width: 2px; height: {}px; top: {}px; left: {}px; display: {};", h, y, x as i32 - 1, if self.cursor2.0 == self.cursor2.1 { "block" } else { "None"});
-        let s_small = format!("background-color: #7799bb; position: absolute; width: 2px; height: {}px; top: {}px; left: {}px; display: {};", h, h*self.cursor_small.0 as f32, w * self.cursor_small.1 as f32 - 1.0, if (self.cursor2.0).0 != (self.cursor2.1).0 { "block" } else { "None"});
+        let x = (self.editor.cursor2.0).1 as f32 * w;
+        let y = (self.editor.cursor2.0).0 as f32 * h;
+        let s = format!("background-color: #7799bb; position: absolute; width: 2px; height: {}px; top: {}px; left: {}px; display: {};", h, y, x as i32 - 1, if self.editor.cursor2.0 == self.editor.cursor2.1 { "block" } else { "None"});
+        let s_small = format!("background-color: #7799bb; position: absolute; width: 2px; height: {}px; top: {}px; left: {}px; display: {};", h, h*self.editor.cursor_small.0 as f32, w * self.editor.cursor_small.1 as f32 - 1.0, if (self.editor.cursor2.0).0 != (self.editor.cursor2.1).0 { "block" } else { "None"});
         
         // cursor
-        let width_first_line = w * if (self.cursor2.0).0 == (self.cursor2.1).0 { 
-            (self.cursor2.1).1 - (self.cursor2.0).1 
-        } else {
-            self.window_width - (self.cursor2.0).1
-        } as f32;
-        let first_line_style = format!("top: {}px; left: {}px; width: {}px; height: {}px;", h*(self.cursor2.0).0 as f32, w*(self.cursor2.0).1 as f32, width_first_line, h);
-        let num_mid_lines = ((self.cursor2.1).0 - (self.cursor2.0).0).checked_sub(1).unwrap_or(0);
-        let mid_lines_style = format!("top: {}px; left: 0px; width: {}px; height: {}px;", h*((self.cursor2.0).0 + 1) as f32, w * self.window_width as f32, h*num_mid_lines as f32);
-        let last_line_width = if (self.cursor2.0).0 == (self.cursor2.1).0 { 
-            0
-        }else{
-            (self.cursor2.1).1
+        let (first_line_style, mid_lines_style, last_line_style) = self.area_styles(self.editor.cursor2);
+        let (sel_first_line_style, sel_mid_lines_style, sel_last_line_style) = match self.editor.selection {
+            Some(area) => self.area_styles(area),
+            None => Default::default(),
+        };
+        let (bracket_style, match_style) = match self.editor.bracket_match {
+            Some((bracket, matching)) => (self.area_styles(bracket).0, self.area_styles(matching).0),
+            None => Default::default(),
         };
-        let last_line_style = format!("top: {}px; left: 0px; width: {}px; height: {}px;", h*(self.cursor2.1).0 as f32, w * last_line_width as f32, h);
         let div_style = format!("font-family: monospace; position: relative; font-size: {}pt;", TEXT_SIZE);
+        let gutter_style = format!("font-family: monospace; font-size: {}pt; padding-right: 10px;", TEXT_SIZE);
+
+        let active = |on: bool| if on { "active" } else { "" };
+        let case_class = active(self.editor.search.options.case_sensitive);
+        let word_class = active(self.editor.search.options.whole_word);
+        let regex_class = active(self.editor.search.options.regex);
+        let whitespace_class = active(self.editor.search.options.ignore_whitespace);
+        let search_status = match &self.editor.search_error {
+            Some(e) => e.clone(),
+            None => format!("{} matches", self.editor.search_matches.len()),
+        };
+
+        let (warning_class, warning) = match &self.editor.token_warning {
+            Some(w) => ("token-warning", format!("Tokens are joined: {}", w)),
+            None => ("token-warning hidden", "".to_string()),
+        };
+
+        let theme = if self.dark_theme { "theme-dark" } else { "theme-light" };
 
         html! {
-            <div  >
+            <div class=theme, >
                 <nav class="menu",>
                     <button onclick=|_| Msg::ClearVirtualWhitespace,>{ "Clear virtual whitespace" }</button>
+                    <button onclick=|_| Msg::MaterializeWhitespace,>{ "Materialize whitespace" }</button>
+                    <button onclick=|_| Msg::DematerializeWhitespace,>{ "Dematerialize whitespace" }</button>
                     <button onclick=|_| Msg::Format,>{ "Update virtual whitespace" }</button>
-                    <button onclick=|_| Msg::ToggleAutoUpdate,>{ if self.auto_update {"Auto update ON"} else {"Auto update OFF"} }</button>
+                    <button onclick=|_| Msg::FoldItems,>{ "Fold items" }</button>
+                    <button onclick=|_| Msg::UnfoldAll,>{ "Unfold all" }</button>
+                    <button onclick=|_| Msg::ToggleAutoUpdate,>{ if self.editor.auto_update {"Auto update ON"} else {"Auto update OFF"} }</button>
+                    <button onclick=|_| Msg::ToggleAutoClose,>{ if self.editor.auto_close {"Auto close ON"} else {"Auto close OFF"} }</button>
+                    <button onclick=|_| Msg::ToggleTypedLines,>{ if self.show_typed_lines {"Typed line numbers ON"} else {"Typed line numbers OFF"} }</button>
+                    <button onclick=|_| Msg::SetKeymapPreset(Preset::Default), disabled=self.editor.keymap.preset() == Preset::Default,>{ "Default keys" }</button>
+                    <button onclick=|_| Msg::SetKeymapPreset(Preset::Emacs), disabled=self.editor.keymap.preset() == Preset::Emacs,>{ "Emacs keys" }</button>
+                    <button onclick=|_| Msg::SetKeymapPreset(Preset::Vim), disabled=self.editor.keymap.preset() == Preset::Vim,>{ "Vim keys" }</button>
+                    <button onclick=|_| Msg::ToggleTheme,>{ if self.dark_theme {"Dark theme"} else {"Light theme"} }</button>
                     <input oninput=|e| Msg::UpdateWidth(e.value.parse().unwrap()), type="range", min="40", max="150", value="100", class="slider", style="width:500px", />
                 </nav>
-                <div style="width:80%; border: 1px solid grey; padding: 10px;", onkeydown=|e| Msg::KeyEvt(e), tabindex="0", >
-                    <div style=div_style, >
-                        <pre>{ self.text.clone() }</pre>
+                <nav class="find-bar",>
+                    <input oninput=|e| Msg::SetSearchQuery(e.value), placeholder="Find", />
+                    <button onclick=|_| Msg::ToggleCaseSensitive, class=case_class, >{ "Aa" }</button>
+                    <button onclick=|_| Msg::ToggleWholeWord, class=word_class, >{ "Word" }</button>
+                    <button onclick=|_| Msg::ToggleRegex, class=regex_class, >{ ".*" }</button>
+                    <button onclick=|_| Msg::ToggleIgnoreWhitespace, class=whitespace_class, title="Ignore whitespace", >{ "a b" }</button>
+                    <input oninput=|e| Msg::SetReplacement(e.value), placeholder="Replace", />
+                    <button onclick=|_| Msg::ReplaceNext,>{ "Replace" }</button>
+                    <button onclick=|_| Msg::ReplaceAll,>{ "Replace all" }</button>
+                    <span>{ search_status }</span>
+                </nav>
+                <nav class="go-to-bar",>
+                    <input oninput=|e| Msg::SetGoToLine(e.value), placeholder="Line", size="6", />
+                    <button onclick=|_| Msg::ToggleGoToTypedLine,>{ if self.go_to_typed_line {"Typed line"} else {"Display line"} }</button>
+                    <button onclick=|_| Msg::GoToLine,>{ "Go" }</button>
+                    <details class="symbol-palette", >
+                        <summary>{ "Go to symbol" }</summary>
+                        <input oninput=|e| Msg::SetSymbolFilter(e.value), placeholder="Filter symbols", />
+                        { self.view_symbols() }
+                    </details>
+                </nav>
+                <div class=warning_class, >{ warning }</div>
+                <div style="width:80%; border: 1px solid grey; padding: 10px; display: flex;", onkeydown=|e| Msg::KeyEvt(e), tabindex="0", >
+                    <div style=gutter_style, >
+                        <pre class="gutter", >{ self.gutter_text() }</pre>
+                    </div>
+                    <div id="code", style=div_style, onclick=|e| Msg::Click(e), onmousedown=|e| Msg::MouseDown(e), onmousemove=|e| Msg::MouseMove(e), >
+                        <pre class="code", >{ for self.editor.highlighted.iter().map(view_span) }</pre>
                         <div id="cursor", style=s, ></div>
                         <div class="area", style=first_line_style, ></div>
                         <div class="area", style=mid_lines_style, ></div>
                         <div class="area", style=last_line_style, ></div>
+                        <div class="area selection", style=sel_first_line_style, ></div>
+                        <div class="area selection", style=sel_mid_lines_style, ></div>
+                        <div class="area selection", style=sel_last_line_style, ></div>
+                        { for self.editor.search_matches.iter().map(|area| self.view_area(*area, "area search-match")) }
+                        <div class="area bracket-match", style=bracket_style, ></div>
+                        <div class="area bracket-match", style=match_style, ></div>
                         <div id="cursor_small", style=s_small, ></div>
-                        <pre>{ format!("{}|", " ".repeat(self.window_width)) }</pre>
+                        { for self.editor.extra_cursors.iter().map(|c| self.view_extra_cursor(c)) }
+                        { self.view_block_selection() }
+                        <pre>{ format!("{}|", " ".repeat(self.editor.window_width)) }</pre>
                     </div>
                 </div>
+                <details>
+                    <summary>{ "Keymap config (TOML or JSON)" }</summary>
+                    <textarea rows="8", cols="60", oninput=|e| Msg::LoadKeymapConfig(e.value), placeholder="preset = \"Emacs\"\n[bindings]\n\"Ctrl+s\" = \"Format\"", />
+                </details>
+                <details>
+                    <summary>{ "Typed vs. virtual whitespace" }</summary>
+                    <pre class="diff", >{ for self.editor.content.whitespace_diff().iter().map(view_diff_span) }</pre>
+                </details>
+                <details>
+                    <summary>{ "Saved file (minimal whitespace)" }</summary>
+                    <button onclick=|_| Msg::Save,>{ "Save" }</button>
+                    <button onclick=|_| Msg::Load,>{ "Load" }</button>
+                    <textarea rows="8", cols="60", value=&self.saved, oninput=|e| Msg::SetSaved(e.value), />
+                </details>
+                <StatusBar:
+                    display_pos=self.editor.cursor2.0,
+                    typed_pos=self.editor.content.typed_cursor_pos(),
+                    in_virtual_whitespace=self.editor.content.cursor_in_virtual_whitespace(),
+                    format_status=self.editor.format_status,
+                    stats=self.editor.format_stats.clone(),
+                    width=self.editor.window_width,
+                    mode=self.mode_text(),
+                    />
             </div>
         }
     }
-}
\ No newline at end of file
+}
+
+fn view_symbol(symbol: &Symbol) -> Html<Model> {
+    let start = symbol.start;
+    let indent = format!("padding-left: {}em;", symbol.depth * 2);
+    html! {
+        <li style=indent, onclick=move |_| Msg::GoToSymbol(start), >
+            <span class="tok-keyword", >{ symbol.kind.keyword() }</span>{ " " }{ symbol.name.clone() }
+        </li>
+    }
+}
+
+// changed whitespace is made visible, typed whitespace the layout doesn't have is struck out
+fn view_diff_span((diff, s): &(WhitespaceDiff, String)) -> Html<Model> {
+    let class = match diff {
+        WhitespaceDiff::Unchanged => "diff-unchanged",
+        WhitespaceDiff::Overridden => "diff-overridden",
+        WhitespaceDiff::Supplemented => "diff-supplemented",
+    };
+    let text = if *diff == WhitespaceDiff::Unchanged {
+        s.clone()
+    } else {
+        s.replace(' ', "·").replace('\n', "¶\n")
+    };
+    html! {
+        <span class=class, >{ text }</span>
+    }
+}
+
+fn view_span((kind, s): &(Option<TokenKind>, String)) -> Html<Model> {
+    let class = kind.map(|k| k.css_class()).unwrap_or("tok-none");
+    html! {
+        <span class=class, >{ s.clone() }</span>
+    }
+}
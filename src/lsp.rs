@@ -0,0 +1,276 @@
+// A language server (LSP over stdio) for the virtual whitespace formatter.
+//
+// Besides `textDocument/formatting`, it answers the custom request `tokenEditor/virtualWhitespace`
+// with the virtual whitespace the token editor would display, so other editors can show it as
+// decorations: every entry has the typed position it's displayed at and its newlines and
+// spaces. Documents are synced in full.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value};
+
+use crate::content::{self, Content};
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+pub const VIRTUAL_WHITESPACE_METHOD: &str = "tokenEditor/virtualWhitespace";
+
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<Value>> {
+    match read_frame(reader)? {
+        Some(body) => serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+        None => Ok(None),
+    }
+}
+
+// the body of the next message, `None` at the end of the input
+fn read_frame<R: BufRead>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if header.starts_with("Content-Length:") {
+            length = header["Content-Length:".len()..].trim().parse::<usize>().ok();
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+pub fn write_message<W: Write>(writer: &mut W, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+// LSP positions count UTF-16 code units
+fn position(text: &str, line: usize, col: usize) -> Value {
+    let character: usize = text.lines().nth(line).unwrap_or("").chars().take(col).map(char::len_utf16).sum();
+    json!({ "line": line, "character": character })
+}
+
+fn end_position(text: &str) -> Value {
+    let line = text.matches('\n').count();
+    let last = text.rsplit('\n').next().unwrap_or("");
+    json!({ "line": line, "character": last.encode_utf16().count() })
+}
+
+pub struct Server {
+    documents: HashMap<String, String>,
+    width: usize,
+    shutdown: bool,
+}
+
+impl Default for Server {
+    fn default() -> Server {
+        Server { documents: HashMap::new(), width: 100, shutdown: false }
+    }
+}
+
+impl Server {
+    fn document(&self, params: &Value) -> Result<&String, String> {
+        let uri = params["textDocument"]["uri"].as_str().ok_or("missing textDocument.uri")?;
+        self.documents.get(uri).ok_or_else(|| format!("unknown document {}", uri))
+    }
+
+    fn formatting(&self, params: &Value) -> Result<Value, String> {
+        let text = self.document(params)?;
+        match content::prettify_code(text.clone(), self.width) {
+            Some(ref formatted) if formatted != text => Ok(json!([{
+                "range": { "start": { "line": 0, "character": 0 }, "end": end_position(text) },
+                "newText": formatted,
+            }])),
+            _ => Ok(json!([])),
+        }
+    }
+
+    fn virtual_whitespace(&self, params: &Value) -> Result<Value, String> {
+        let text = self.document(params)?;
+        let width = params["width"].as_u64().map_or(self.width, |w| w as usize);
+        let mut content = Content::from_string(text);
+        if content.update_virtual_whitespace(width).is_none() {
+            return Err("rustfmt can't format the document".to_string());
+        }
+        Ok(content.virtual_padding().into_iter().map(|((line, col), newlines, spaces)| json!({
+            "position": position(text, line, col),
+            "newlines": newlines,
+            "spaces": spaces,
+        })).collect())
+    }
+
+    // the result of a request, `Ok(None)` for notifications
+    fn dispatch(&mut self, method: &str, params: &Value) -> Result<Option<Value>, (i64, String)> {
+        let invalid = |e: String| (INVALID_PARAMS, e);
+        match method {
+            "initialize" => {
+                if let Some(width) = params["initializationOptions"]["maxWidth"].as_u64() {
+                    self.width = width as usize;
+                }
+                Ok(Some(json!({
+                    "capabilities": {
+                        "textDocumentSync": 1,
+                        "documentFormattingProvider": true,
+                        "experimental": { "virtualWhitespaceProvider": true },
+                    },
+                    "serverInfo": { "name": "token_editor" },
+                })))
+            },
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Some(Value::Null))
+            },
+            "textDocument/didOpen" => {
+                let doc = &params["textDocument"];
+                if let (Some(uri), Some(text)) = (doc["uri"].as_str(), doc["text"].as_str()) {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+                Ok(None)
+            },
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str();
+                let text = params["contentChanges"].as_array().and_then(|c| c.last()).and_then(|c| c["text"].as_str());
+                if let (Some(uri), Some(text)) = (uri, text) {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                }
+                Ok(None)
+            },
+            "textDocument/didClose" => {
+                if let Some(uri) = params["textDocument"]["uri"].as_str() {
+                    self.documents.remove(uri);
+                }
+                Ok(None)
+            },
+            "textDocument/formatting" => self.formatting(params).map(Some).map_err(invalid),
+            VIRTUAL_WHITESPACE_METHOD => self.virtual_whitespace(params).map(Some).map_err(invalid),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method {}", method))),
+        }
+    }
+
+    // the response to a message, if it's a request
+    pub fn handle(&mut self, message: &Value) -> Option<Value> {
+        let method = message["method"].as_str().unwrap_or("");
+        let result = self.dispatch(method, &message["params"]);
+        let id = message.get("id")?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result.unwrap_or(Value::Null) }),
+            Err((code, e)) => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": e } }),
+        })
+    }
+
+    // serves until `exit`. Returns whether `shutdown` came first, i.e. the exit code should be 0.
+    pub fn run<R: BufRead, W: Write>(&mut self, reader: &mut R, writer: &mut W) -> io::Result<bool> {
+        while let Some(body) = read_frame(reader)? {
+            // only a broken frame ends the server, the next message after invalid JSON is fine
+            let message: Value = match serde_json::from_slice(&body) {
+                Ok(message) => message,
+                Err(e) => {
+                    let error = json!({ "jsonrpc": "2.0", "id": null, "error": { "code": PARSE_ERROR, "message": e.to_string() } });
+                    write_message(writer, &error)?;
+                    continue;
+                },
+            };
+            if message["method"] == "exit" {
+                break;
+            }
+            if let Some(response) = self.handle(&message) {
+                write_message(writer, &response)?;
+            }
+        }
+        Ok(self.shutdown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // runs a server on the framed `messages` and returns its responses
+    fn run_client(messages: &[Value]) -> (bool, Vec<Value>) {
+        let mut input = vec!();
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        run_input(input)
+    }
+
+    fn run_input(input: Vec<u8>) -> (bool, Vec<Value>) {
+        let mut output = vec!();
+        let clean_exit = Server::default().run(&mut io::Cursor::new(input), &mut output).unwrap();
+        let mut reader = io::Cursor::new(output);
+        let mut responses = vec!();
+        while let Some(response) = read_message(&mut reader).unwrap() {
+            responses.push(response);
+        }
+        (clean_exit, responses)
+    }
+
+    fn open(text: &str) -> Value {
+        json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+            "textDocument": { "uri": "file:///a.rs", "languageId": "rust", "version": 1, "text": text },
+        }})
+    }
+
+    fn request(id: u64, method: &str) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": { "textDocument": { "uri": "file:///a.rs" } } })
+    }
+
+    #[test]
+    fn test_session() {
+        let (clean_exit, responses) = run_client(&[
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": { "capabilities": {} } }),
+            json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }),
+            open("fn a(){\"😀\";1}"),
+            request(2, "textDocument/formatting"),
+            request(3, VIRTUAL_WHITESPACE_METHOD),
+            request(4, "textDocument/hover"),
+            json!({ "jsonrpc": "2.0", "id": 5, "method": "shutdown" }),
+            json!({ "jsonrpc": "2.0", "method": "exit" }),
+        ]);
+        assert!(clean_exit);
+        assert_eq!(responses.len(), 5);
+        assert_eq!(responses[0]["result"]["capabilities"]["documentFormattingProvider"], true);
+        assert_eq!(responses[1]["result"], json!([{
+            "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 14 } },
+            "newText": "fn a() {\n    \"😀\";\n    1\n}\n",
+        }]));
+        assert_eq!(responses[2]["result"], json!([
+            { "position": { "line": 0, "character": 6 }, "newlines": 0, "spaces": 1 },
+            { "position": { "line": 0, "character": 7 }, "newlines": 1, "spaces": 4 },
+            { "position": { "line": 0, "character": 12 }, "newlines": 1, "spaces": 4 },
+            { "position": { "line": 0, "character": 13 }, "newlines": 1, "spaces": 0 },
+            { "position": { "line": 0, "character": 14 }, "newlines": 1, "spaces": 0 },
+        ]));
+        assert_eq!(responses[3]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(responses[4]["id"], 5);
+    }
+
+    #[test]
+    fn test_invalid_json() {
+        let mut input = vec!();
+        write_message(&mut input, &json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} })).unwrap();
+        input.extend_from_slice(b"Content-Length: 5\r\n\r\n{oops");
+        write_message(&mut input, &json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" })).unwrap();
+        write_message(&mut input, &json!({ "jsonrpc": "2.0", "method": "exit" })).unwrap();
+        let (clean_exit, responses) = run_input(input);
+        assert!(clean_exit);
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[1]["id"], Value::Null);
+        assert_eq!(responses[1]["error"]["code"], PARSE_ERROR);
+        assert_eq!(responses[2]["id"], 2);
+
+        // without a Content-Length, there's no telling where the next message starts
+        let mut input = b"Content-Type: x\r\n\r\n".to_vec();
+        write_message(&mut input, &json!({ "jsonrpc": "2.0", "method": "exit" })).unwrap();
+        assert!(Server::default().run(&mut io::Cursor::new(input), &mut vec!()).is_err());
+    }
+}
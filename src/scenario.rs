@@ -0,0 +1,109 @@
+// Scripted key sequences for testing `Editor`. A script has one step per line:
+//
+//   type `fn a(){}`            types the text, `\n` presses Enter
+//   press ArrowLeft Ctrl+z     presses the chords (see `KeyChord::from_str`)
+//   set auto_update on         sets auto_update, auto_close (on/off) or width (a number)
+//   expect text `...`          the displayed text, including virtual whitespace
+//   expect typed `...`         the typed text
+//   expect cursor2 0:9 1:4     both ends of the cursor, as line:column
+//   expect cursor_small 0:8    the small cursor
+//
+// Empty lines and lines starting with `#` are skipped. Failures panic with the line of the step.
+
+use crate::content::Content;
+use crate::editor::Editor;
+use crate::keymap::KeyChord;
+
+// the text between the first and the last backtick
+fn quoted(arg: &str) -> Result<String, String> {
+    match (arg.find('`'), arg.rfind('`')) {
+        (Some(start), Some(end)) if start < end => Ok(arg[start + 1..end].replace("\\n", "\n")),
+        _ => Err(format!("expected text in backticks, got `{}`", arg)),
+    }
+}
+
+fn position(arg: &str) -> Result<(usize, usize), String> {
+    let mut parts = arg.split(':').map(|n| n.parse::<usize>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(line)), Some(Ok(col)), None) => Ok((line, col)),
+        _ => Err(format!("expected line:column, got `{}`", arg)),
+    }
+}
+
+fn flag(arg: &str) -> Result<bool, String> {
+    match arg {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected on or off, got `{}`", arg)),
+    }
+}
+
+fn step(editor: &mut Editor, line: &str) -> Result<(), String> {
+    let (command, arg) = match line.find(' ') {
+        Some(i) => (&line[..i], line[i + 1..].trim()),
+        None => (line, ""),
+    };
+    match command {
+        "type" => {
+            for c in quoted(arg)?.chars() {
+                let chord = if c == '\n' { KeyChord::plain("Enter") } else { KeyChord::plain(&c.to_string()) };
+                editor.handle_key(&chord);
+            }
+        },
+        "press" => {
+            for chord in arg.split_whitespace() {
+                editor.handle_key(&chord.parse()?);
+            }
+        },
+        "set" => {
+            let mut words = arg.split_whitespace();
+            match (words.next(), words.next()) {
+                (Some("auto_update"), Some(value)) => editor.auto_update = flag(value)?,
+                (Some("auto_close"), Some(value)) => editor.auto_close = flag(value)?,
+                (Some("width"), Some(value)) => {
+                    let width = value.parse().map_err(|_| format!("invalid width `{}`", value))?;
+                    editor.set_width(width);
+                },
+                _ => return Err(format!("unknown setting `{}`", arg)),
+            }
+        },
+        "expect" => {
+            let (what, expected) = match arg.find(' ') {
+                Some(i) => (&arg[..i], arg[i + 1..].trim()),
+                None => (arg, ""),
+            };
+            let (actual, expected) = match what {
+                "text" => (editor.text(), quoted(expected)?),
+                "typed" => (editor.content.typed_string(), quoted(expected)?),
+                "cursor2" => {
+                    let mut ends = expected.split_whitespace();
+                    let start = position(ends.next().unwrap_or(""))?;
+                    let end = position(ends.next().unwrap_or(""))?;
+                    (format!("{:?}", editor.cursor2), format!("{:?}", (start, end)))
+                },
+                "cursor_small" => (format!("{:?}", editor.cursor_small), format!("{:?}", position(expected)?)),
+                _ => return Err(format!("can't expect `{}`", what)),
+            };
+            if actual != expected {
+                return Err(format!("expected {} {:?}, got {:?}", what, expected, actual));
+            }
+        },
+        _ => return Err(format!("unknown step `{}`", command)),
+    }
+    Ok(())
+}
+
+// runs the script on an editor for `text`
+pub fn run(text: &str, script: &str) -> Editor {
+    let mut editor = Editor::new(Content::from_string(text));
+    for (i, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Err(e) = step(&mut editor, line) {
+            panic!("line {} `{}`: {}", i + 1, line, e);
+        }
+    }
+    editor
+}
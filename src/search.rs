@@ -0,0 +1,263 @@
+// Find and replace over the typed text.
+//
+// Virtual whitespace isn't part of the typed text, so `a+b` finds `a+b` even if it's displayed
+// as `a + b`. Matches are ranges of cursor positions.
+//
+// With `ignore_whitespace`, the search runs on the `Elmt::character` sequence instead, so any
+// whitespace, typed or virtual, matches any whitespace or none at all. A regex is taken as it is,
+// there's no whitespace left for the whitespace in it to match.
+
+use regex::{Captures, Regex, RegexBuilder};
+
+use crate::content::Content;
+use crate::lexer;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub regex: bool,
+    pub ignore_whitespace: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Search {
+    pub query: String,
+    pub options: SearchOptions,
+}
+
+impl Search {
+    pub fn new(query: &str, options: SearchOptions) -> Search {
+        Search { query: query.to_string(), options }
+    }
+
+    fn build_regex(&self) -> Result<Regex, String> {
+        let pattern = if self.options.regex {
+            self.query.clone()
+        } else if self.options.ignore_whitespace {
+            regex::escape(&self.query.split_whitespace().collect::<String>())
+        } else {
+            regex::escape(&self.query)
+        };
+        // on the character sequence, word boundaries are checked on the elmts, see `find_elmt_ranges`
+        let pattern = if self.options.whole_word && !self.options.ignore_whitespace {
+            format!(r"\b(?:{})\b", pattern)
+        } else {
+            pattern
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(!self.options.case_sensitive)
+            .build()
+            .map_err(|e| e.to_string())
+    }
+
+    // all non-empty matches in the typed text
+    pub fn find_all(&self, content: &Content) -> Result<Vec<((usize, usize), (usize, usize))>, String> {
+        Ok(self.captures(content, |_| ())?.into_iter().map(|(m, ())| m).collect())
+    }
+
+    // all non-empty matches with `f` of their captures. The captures are taken on the whole text,
+    // so look-arounds and anchors in a regex see the same text when replacing as when finding.
+    fn captures<T>(&self, content: &Content, f: impl Fn(&Captures) -> T) -> Result<Vec<(((usize, usize), (usize, usize)), T)>, String> {
+        if self.query.is_empty() {
+            return Ok(vec!());
+        }
+        if self.options.ignore_whitespace {
+            return Ok(self.elmt_captures(content, f)?.into_iter()
+                .map(|((start, end), t)| ((content.position_before_char(start), (end, 0)), t))
+                .collect());
+        }
+        let re = self.build_regex()?;
+        let text = content.typed_string();
+        Ok(re.captures_iter(&text)
+            .filter_map(|caps| {
+                let m = caps.get(0)?;
+                if m.start() == m.end() {
+                    return None;
+                }
+                let start = text[..m.start()].chars().count();
+                let end = start + m.as_str().chars().count();
+                Some(((content.cursor_at_typed_offset(start), content.cursor_at_typed_offset(end)), f(&caps)))
+            })
+            .collect())
+    }
+
+    // matches on the character sequence as elmt ranges, `end` is exclusive
+    pub fn find_elmt_ranges(&self, content: &Content) -> Result<Vec<(usize, usize)>, String> {
+        Ok(self.elmt_captures(content, |_| ())?.into_iter().map(|(range, ())| range).collect())
+    }
+
+    fn elmt_captures<T>(&self, content: &Content, f: impl Fn(&Captures) -> T) -> Result<Vec<((usize, usize), T)>, String> {
+        if self.query.trim().is_empty() {
+            return Ok(vec!());
+        }
+        let re = self.build_regex()?;
+        // comments and literals keep their whitespace as elmt characters, it's skipped as well.
+        // The `k`th char belongs to elmt `elmts[k]`.
+        let (elmts, chars): (Vec<usize>, String) = (0..content.num_elmts() - 1)
+            .map(|i| (i, content.char_at(i)))
+            .filter(|(_, c)| !c.is_whitespace())
+            .unzip();
+        // a word continues over elmts only if there's no whitespace between them
+        let inside_word = |i: usize| lexer::is_ident_continue(content.char_at(i)) && content.continues_word(i);
+        Ok(re.captures_iter(&chars)
+            .filter_map(|caps| {
+                let m = caps.get(0)?;
+                if m.start() == m.end() {
+                    return None;
+                }
+                let start = chars[..m.start()].chars().count();
+                let last = start + m.as_str().chars().count() - 1;
+                Some(((elmts[start], elmts[last] + 1), caps))
+            })
+            .filter(|&((start, end), _)| !self.options.whole_word || (!inside_word(start) && !inside_word(end)))
+            .map(|(range, caps)| (range, f(&caps)))
+            .collect())
+    }
+
+    // the first match starting after the cursor (or before it when searching backwards),
+    // wrapping around at the end of the text
+    pub fn find_next(&self, content: &Content, forward: bool) -> Result<Option<((usize, usize), (usize, usize))>, String> {
+        let matches = self.find_all(content)?;
+        let cursor = content.cursor();
+        let next = if forward {
+            matches.iter().find(|m| m.0 > cursor).or_else(|| matches.first())
+        } else {
+            matches.iter().rev().find(|m| m.0 < cursor).or_else(|| matches.last())
+        };
+        Ok(next.cloned())
+    }
+
+    // the replacement for a match, with `$1`-style references expanded in regex mode
+    fn expand_replacement(&self, caps: &Captures, replacement: &str) -> String {
+        if !self.options.regex {
+            return replacement.to_string();
+        }
+        let mut expanded = String::new();
+        caps.expand(replacement, &mut expanded);
+        expanded
+    }
+
+    // replaces the match at the cursor (or the next one) and moves the cursor behind it
+    pub fn replace_next(&self, content: &mut Content, replacement: &str) -> Result<bool, String> {
+        let cursor = content.cursor();
+        let matches = self.captures(content, |caps| self.expand_replacement(caps, replacement))?;
+        let m = matches.iter().find(|m| (m.0).0 >= cursor).or_else(|| matches.first()).cloned();
+        match m {
+            Some(((start, end), expanded)) => {
+                content.delete_range(start, end);
+                content.insert_str(&expanded);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    // returns the number of replaced matches
+    pub fn replace_all(&self, content: &mut Content, replacement: &str) -> Result<usize, String> {
+        let matches = self.captures(content, |caps| self.expand_replacement(caps, replacement))?;
+        // from the back, so the positions of the remaining matches stay valid
+        for ((start, end), expanded) in matches.iter().rev() {
+            content.delete_range(*start, *end);
+            content.insert_str(expanded);
+        }
+        Ok(matches.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(content: &Content, search: &Search) -> Vec<String> {
+        search.find_all(content).unwrap().into_iter()
+            .map(|(start, end)| {
+                let (start, end) = (content.typed_offset(start), content.typed_offset(end));
+                content.typed_string().chars().skip(start).take(end - start).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_ignores_virtual_whitespace() {
+        let c = Content::from_strings("let x=a+b;", "let x = a + b;");
+        let search = Search::new("a+b", SearchOptions::default());
+        assert_eq!(found(&c, &search), vec!("a+b"));
+        let search = Search::new("a + b", SearchOptions::default());
+        assert!(found(&c, &search).is_empty());
+    }
+
+    #[test]
+    fn test_find_options() {
+        let c = Content::from_string("let foo = Foo; foobar");
+        let plain = Search::new("foo", SearchOptions::default());
+        assert_eq!(found(&c, &plain), vec!("foo", "Foo", "foo"));
+        let case = Search::new("foo", SearchOptions { case_sensitive: true, ..Default::default() });
+        assert_eq!(found(&c, &case), vec!("foo", "foo"));
+        let word = Search::new("foo", SearchOptions { whole_word: true, ..Default::default() });
+        assert_eq!(found(&c, &word), vec!("foo", "Foo"));
+        let re = Search::new(r"f\w+r", SearchOptions { regex: true, ..Default::default() });
+        assert_eq!(found(&c, &re), vec!("foobar"));
+        let invalid = Search::new("(", SearchOptions { regex: true, ..Default::default() });
+        assert!(invalid.find_all(&c).is_err());
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut c = Content::from_strings("fn a(){x=1;y=x;}", "fn a() {\n    x = 1;\n    y = x;\n}");
+        let search = Search::new("x", SearchOptions { whole_word: true, ..Default::default() });
+        assert!(search.replace_next(&mut c, "value").unwrap());
+        assert_eq!(&c.typed_string(), "fn a(){value=1;y=x;}");
+        assert_eq!(search.replace_all(&mut c, "z").unwrap(), 1);
+        assert_eq!(&c.typed_string(), "fn a(){value=1;y=z;}");
+
+        let search = Search::new(r"(\w)=(\w)", SearchOptions { regex: true, ..Default::default() });
+        assert_eq!(search.replace_all(&mut c, "$2=$1").unwrap(), 2);
+        assert_eq!(&c.typed_string(), "fn a(){valu1=e;z=y;}");
+
+        // the groups are the ones of the match in the whole text, `b` alone would match `^(\w)`
+        let mut c = Content::from_string("ab ab");
+        let search = Search::new(r"^(\w)|(\w)$", SearchOptions { regex: true, ..Default::default() });
+        assert_eq!(search.replace_all(&mut c, "$1-").unwrap(), 2);
+        assert_eq!(&c.typed_string(), "a-b a-");
+
+        // extra cursors stay with the text when it shrinks
+        let mut c = Content::from_string("aaaa bbbb cc");
+        c.add_cursor(c.position_before_char(8));
+        let search = Search::new("aaaa|bbbb", SearchOptions { regex: true, ..Default::default() });
+        assert_eq!(search.replace_all(&mut c, "x").unwrap(), 2);
+        assert_eq!(&c.typed_string(), "x x cc");
+        assert_eq!(c.extra_cursors(), &[c.position_before_char(2)]);
+        assert_eq!(c.extra_cursor_positions().len(), 1);
+    }
+
+    #[test]
+    fn test_ignore_whitespace() {
+        let c = Content::from_strings("fn a(x:u8){let y=x + 1;}", "fn a(x: u8) {\n    let y = x + 1;\n}");
+        let options = SearchOptions { ignore_whitespace: true, ..Default::default() };
+        let ranges = Search::new("x+1", options).find_elmt_ranges(&c).unwrap();
+        assert_eq!(ranges.iter().map(|&(a, b)| c.chars_of(a, b)).collect::<Vec<_>>(), vec!("x+1"));
+        assert_eq!(found(&c, &Search::new("(x: u8) {", options)), vec!("(x:u8){"));
+        assert_eq!(found(&c, &Search::new("lety", options)), vec!("let y"));
+
+        let word = SearchOptions { whole_word: true, ..options };
+        assert_eq!(found(&c, &Search::new("x", word)), vec!("x", "x"));
+        assert!(found(&c, &Search::new("le", word)).is_empty());
+        let re = SearchOptions { regex: true, ..options };
+        assert_eq!(found(&c, &Search::new(r"y=\w", re)), vec!("y=x"));
+        assert!(found(&c, &Search::new(r"y = \w", re)).is_empty());
+        assert!(found(&c, &Search::new(r"x#", re)).is_empty());
+    }
+
+    #[test]
+    fn test_ignore_whitespace_in_comments() {
+        let mut c = Content::from_string("// one  two\nfn a(){\"one two\"}");
+        let options = SearchOptions { ignore_whitespace: true, ..Default::default() };
+        assert_eq!(found(&c, &Search::new("one two", options)), vec!("one  two", "one two"));
+        assert_eq!(found(&c, &Search::new("onetwo", options)), vec!("one  two", "one two"));
+
+        let re = SearchOptions { regex: true, ..options };
+        assert_eq!(Search::new(r"(one)(two)", re).replace_all(&mut c, "$2$1").unwrap(), 2);
+        assert_eq!(&c.typed_string(), "// twoone\nfn a(){\"twoone\"}");
+    }
+}
@@ -0,0 +1,82 @@
+use yew::{html, Component, ComponentLink, Html, Renderable, ShouldRender};
+
+use crate::content::FormatStats;
+use crate::editor::FormatStatus;
+
+#[derive(Clone, PartialEq)]
+pub struct Props {
+    pub display_pos: (usize, usize),
+    pub typed_pos: (usize, usize),
+    pub in_virtual_whitespace: bool,
+    pub format_status: FormatStatus,
+    pub stats: Option<FormatStats>,  // of the last successful formatting
+    pub width: usize,
+    pub mode: String,  // editing mode, empty if not modal
+}
+
+impl Default for Props {
+    fn default() -> Self {
+        Props {
+            display_pos: (0, 0),
+            typed_pos: (0, 0),
+            in_virtual_whitespace: false,
+            format_status: FormatStatus::Pending,
+            stats: None,
+            width: 0,
+            mode: "".to_string(),
+        }
+    }
+}
+
+pub struct StatusBar {
+    props: Props,
+}
+
+impl Component for StatusBar {
+    type Message = ();
+    type Properties = Props;
+
+    fn create(props: Self::Properties, _: ComponentLink<Self>) -> Self {
+        StatusBar { props }
+    }
+
+    fn update(&mut self, _: Self::Message) -> ShouldRender {
+        false
+    }
+
+    fn change(&mut self, props: Self::Properties) -> ShouldRender {
+        self.props = props;
+        true
+    }
+}
+
+impl Renderable<StatusBar> for StatusBar {
+    fn view(&self) -> Html<Self> {
+        let p = &self.props;
+        // lines and columns are displayed 1-based
+        let display = format!("Ln {}, Col {}", p.display_pos.0 + 1, p.display_pos.1 + 1);
+        let typed = format!("Typed Ln {}, Col {}", p.typed_pos.0 + 1, p.typed_pos.1 + 1);
+        let virt = if p.in_virtual_whitespace { "virtual whitespace" } else { "" };
+        let (status, status_class) = match p.format_status {
+            FormatStatus::Ok => ("rustfmt: ok", "status-ok"),
+            FormatStatus::Error => ("rustfmt: error", "status-error"),
+            FormatStatus::Pending => ("rustfmt: pending", "status-pending"),
+        };
+        let ratio = match &p.stats {
+            Some(stats) => format!("{} / {} chars typed ({}%)", stats.typed_chars, stats.displayed_chars, stats.ratio()),
+            None => "".to_string(),
+        };
+
+        html! {
+            <div class="status-bar", >
+                <span>{ p.mode.clone() }</span>
+                <span>{ display }</span>
+                <span>{ typed }</span>
+                <span>{ virt }</span>
+                <span class=status_class, >{ status }</span>
+                <span>{ format!("Width {}", p.width) }</span>
+                <span>{ ratio }</span>
+            </div>
+        }
+    }
+}
@@ -0,0 +1,1188 @@
+// A syntax tree of the typed text, parsed from the tokens of `Content`.
+//
+// Items, statements and expressions are parsed the way rustc does, so `a + b * c` contains
+// `b * c`. Patterns, types and the arguments of macros are only split at brackets and commas. The
+// parser never fails: whatever it doesn't understand, e.g. while typing, ends up as plain tokens.
+// All ranges are elmt indices, `end` is exclusive.
+
+use crate::content::Content;
+use crate::lexer::{Token, TokenKind};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    Token,
+    Group,       // brackets including their content
+    Expression,
+    Element,     // a parameter, field, match arm, pattern or type
+    Statement,
+    Item,
+    File,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub kind: NodeKind,
+    pub start: usize,
+    pub end: usize,
+    pub children: Vec<Node>,
+}
+
+// a node spanning the children, or the only child itself
+fn node(kind: NodeKind, mut children: Vec<Node>) -> Node {
+    if children.len() == 1 && (kind == NodeKind::Expression || kind == NodeKind::Element) {
+        return children.pop().unwrap();
+    }
+    let (start, end) = (children[0].start, children.last().unwrap().end);
+    Node { kind, start, end, children }
+}
+
+fn closing(open: &str) -> Option<&'static str> {
+    match open {
+        "(" => Some(")"),
+        "[" => Some("]"),
+        "{" => Some("}"),
+        _ => None,
+    }
+}
+
+fn is_closing(s: &str) -> bool {
+    s == ")" || s == "]" || s == "}"
+}
+
+// the leaves of the parser: tokens and bracket groups
+enum Tree {
+    Token(usize),
+    Group(usize, Vec<Tree>, Option<usize>),  // the brackets and the trees between them
+}
+
+// token trees until a closing bracket (which is not consumed)
+fn token_trees(tokens: &[(Token, String)], pos: &mut usize) -> Vec<Tree> {
+    let mut trees = vec!();
+    while *pos < tokens.len() && !is_closing(&tokens[*pos].1) {
+        let open = *pos;
+        *pos += 1;
+        match closing(&tokens[open].1) {
+            None => trees.push(Tree::Token(open)),
+            Some(close) => {
+                let children = token_trees(tokens, pos);
+                let close = if tokens.get(*pos).map_or(false, |(_, s)| s == close) {
+                    *pos += 1;
+                    Some(*pos - 1)
+                } else {
+                    None
+                };
+                trees.push(Tree::Group(open, children, close));
+            },
+        }
+    }
+    trees
+}
+
+// precedence and right associativity of binary operators, `as` binds tighter than all of them
+fn binary_precedence(op: &str) -> Option<(u8, bool)> {
+    Some(match op {
+        "=" | "+=" | "-=" | "*=" | "/=" | "%=" | "^=" | "&=" | "|=" | "<<=" | ">>=" => (1, true),
+        ".." | "..=" => (RANGE_PRECEDENCE, false),
+        "||" => (3, false),
+        "&&" => (4, false),
+        "==" | "!=" | "<" | ">" | "<=" | ">=" => (COMPARISON_PRECEDENCE, false),
+        "|" => (6, false),
+        "^" => (7, false),
+        "&" => (8, false),
+        "<<" | ">>" => (9, false),
+        "+" | "-" => (10, false),
+        "*" | "/" | "%" => (11, false),
+        _ => return None,
+    })
+}
+
+const AS_PRECEDENCE: u8 = 12;
+
+// a position in a list of trees
+struct Cursor<'a> {
+    trees: &'a [Tree],
+    pos: usize,
+}
+
+type Contents<'a> = fn(&mut Parser<'a>, &mut Cursor<'a>) -> Vec<Node>;
+
+struct Parser<'a> {
+    tokens: &'a [(Token, String)],
+    symbols: Vec<Symbol>,
+    depth: usize,        // number of enclosing symbols
+    macro_depth: usize,  // number of enclosing macro calls, their items aren't symbols
+}
+
+impl<'a> Parser<'a> {
+    fn text(&self, tree: &Tree) -> &'a str {
+        match tree {
+            Tree::Token(i) | Tree::Group(i, _, _) => &self.tokens[*i].1,
+        }
+    }
+
+    fn kind(&self, tree: &Tree) -> TokenKind {
+        match tree {
+            Tree::Token(i) | Tree::Group(i, _, _) => self.tokens[*i].0.kind,
+        }
+    }
+
+    fn is_comment(&self, tree: &Tree) -> bool {
+        self.kind(tree) == TokenKind::Comment
+    }
+
+    // the `n`th tree after the cursor, comments are skipped
+    fn nth(&self, c: &Cursor<'a>, n: usize) -> Option<&'a Tree> {
+        c.trees[c.pos..].iter().filter(|t| !self.is_comment(t)).nth(n)
+    }
+
+    fn nth_text(&self, c: &Cursor<'a>, n: usize) -> Option<&'a str> {
+        self.nth(c, n).map(|t| self.text(t))
+    }
+
+    fn peek(&self, c: &mut Cursor<'a>) -> Option<&'a Tree> {
+        while c.trees.get(c.pos).map_or(false, |t| self.is_comment(t)) {
+            c.pos += 1;
+        }
+        c.trees.get(c.pos)
+    }
+
+    fn peek_text(&self, c: &mut Cursor<'a>) -> Option<&'a str> {
+        self.peek(c).map(|t| self.text(t))
+    }
+
+    fn peek_is(&self, c: &mut Cursor<'a>, text: &str) -> bool {
+        self.peek_text(c) == Some(text)
+    }
+
+    fn peek_kind(&self, c: &mut Cursor<'a>) -> Option<TokenKind> {
+        self.peek(c).map(|t| self.kind(t))
+    }
+
+    fn next(&self, c: &mut Cursor<'a>) -> Option<&'a Tree> {
+        let tree = self.peek(c)?;
+        c.pos += 1;
+        Some(tree)
+    }
+
+    // the next tree, unparsed
+    fn bump(&self, c: &mut Cursor<'a>) -> Node {
+        let tree = self.next(c).unwrap();
+        self.raw(tree)
+    }
+
+    fn token_node(&self, i: usize) -> Node {
+        let t = &self.tokens[i].0;
+        Node { kind: NodeKind::Token, start: t.start, end: t.end, children: vec!() }
+    }
+
+    // a tree whose groups are only split at commas
+    fn raw(&self, tree: &Tree) -> Node {
+        let (open, trees, close) = match tree {
+            Tree::Token(i) => return self.token_node(*i),
+            Tree::Group(open, trees, close) => (open, trees, close),
+        };
+        let mut children = vec!(self.token_node(*open));
+        let mut part = vec!();
+        for tree in trees {
+            if self.text(tree) == "," {
+                if !part.is_empty() {
+                    children.push(node(NodeKind::Element, std::mem::replace(&mut part, vec!())));
+                }
+                children.push(self.raw(tree));
+            } else {
+                part.push(self.raw(tree));
+            }
+        }
+        if !part.is_empty() {
+            children.push(node(NodeKind::Element, part));
+        }
+        if let Some(close) = close {
+            children.push(self.token_node(*close));
+        }
+        node(NodeKind::Group, children)
+    }
+
+    // a group whose contents are parsed by `contents`
+    fn group(&mut self, tree: &'a Tree, contents: Contents<'a>) -> Node {
+        let (open, trees, close) = match tree {
+            Tree::Token(i) => return self.token_node(*i),
+            Tree::Group(open, trees, close) => (open, trees, close),
+        };
+        let mut children = vec!(self.token_node(*open));
+        children.extend(contents(self, &mut Cursor { trees, pos: 0 }));
+        if let Some(close) = close {
+            children.push(self.token_node(*close));
+        }
+        node(NodeKind::Group, children)
+    }
+
+    // the next tree if it's opened by `open`, with its contents parsed
+    fn group_if(&mut self, c: &mut Cursor<'a>, open: &str, contents: Contents<'a>) -> Option<Node> {
+        if !self.peek_is(c, open) {
+            return None;
+        }
+        let tree = self.next(c).unwrap();
+        Some(self.group(tree, contents))
+    }
+
+    // elements separated by commas (or `;` like in `[0; 3]`), each parsed by `element` followed by
+    // whatever it didn't understand
+    fn comma_list(
+        &mut self,
+        c: &mut Cursor<'a>,
+        kind: NodeKind,
+        element: fn(&mut Parser<'a>, &mut Cursor<'a>) -> Option<Node>,
+    ) -> Vec<Node> {
+        let mut nodes = vec!();
+        while let Some(text) = self.peek_text(c) {
+            if text == "," || text == ";" {
+                nodes.push(self.bump(c));
+                continue;
+            }
+            let mut children: Vec<Node> = element(self, c).into_iter().collect();
+            while self.peek_text(c).map_or(false, |t| t != "," && t != ";") {
+                children.push(self.bump(c));
+            }
+            nodes.push(node(kind, children));
+        }
+        nodes
+    }
+
+    fn expressions(&mut self, c: &mut Cursor<'a>) -> Vec<Node> {
+        self.comma_list(c, NodeKind::Expression, |p, c| p.expression(c, false))
+    }
+
+    // the fields of a struct expression
+    fn fields(&mut self, c: &mut Cursor<'a>) -> Vec<Node> {
+        self.comma_list(c, NodeKind::Element, Parser::field)
+    }
+
+    fn field(&mut self, c: &mut Cursor<'a>) -> Option<Node> {
+        if self.nth_text(c, 1) != Some(":") {
+            return self.expression(c, false);
+        }
+        let mut children = vec!(self.bump(c), self.bump(c));
+        children.extend(self.expression(c, false));
+        Some(node(NodeKind::Element, children))
+    }
+
+    // unparsed trees up to one of `stops` outside of `<>`, e.g. a pattern or a type
+    fn raw_until(&mut self, c: &mut Cursor<'a>, stops: &[&str]) -> Vec<Node> {
+        let mut children = vec!();
+        let mut depth = 0;
+        while let Some(text) = self.peek_text(c) {
+            if depth <= 0 && stops.contains(&text) {
+                break;
+            }
+            depth += angle_depth(text);
+            children.push(self.bump(c));
+        }
+        children
+    }
+
+    // generic arguments like `<T, Vec<U>>`
+    fn generics(&mut self, c: &mut Cursor<'a>, children: &mut Vec<Node>) {
+        let mut depth = 0;
+        while let Some(text) = self.peek_text(c) {
+            if text == ";" || text == "{" {
+                break;
+            }
+            depth += angle_depth(text);
+            children.push(self.bump(c));
+            if depth <= 0 {
+                break;
+            }
+        }
+    }
+
+    // the type of an `as` cast
+    fn cast_type(&mut self, c: &mut Cursor<'a>) -> Option<Node> {
+        let mut children = vec!();
+        while let Some(text) = self.peek_text(c) {
+            let kind = self.peek_kind(c);
+            match text {
+                "&" | "&&" | "*" | "mut" | "const" | "dyn" | "::" => children.push(self.bump(c)),
+                "(" | "[" => {
+                    children.push(self.bump(c));
+                    break;
+                },
+                _ if kind == Some(TokenKind::Ident) || PATH_KEYWORDS.contains(&text) => {
+                    children.push(self.bump(c));
+                    if self.peek_is(c, "<") {
+                        self.generics(c, &mut children);
+                    }
+                    if !self.peek_is(c, "::") {
+                        break;
+                    }
+                },
+                _ => break,
+            }
+        }
+        element(children)
+    }
+
+    // the keyword of the item at the cursor and the number of trees before it, e.g. attributes
+    // and qualifiers like `pub(crate) unsafe`
+    fn item_keyword(&self, c: &Cursor<'a>) -> Option<(usize, &'a str)> {
+        let text = |k: usize| self.nth_text(c, k);
+        let mut k = 0;
+        loop {
+            match text(k)? {
+                "#" => {
+                    k += if text(k + 1) == Some("!") { 2 } else { 1 };
+                    if text(k) != Some("[") {
+                        return None;
+                    }
+                    k += 1;
+                },
+                "pub" => k += if text(k + 1) == Some("(") { 2 } else { 1 },
+                "unsafe" | "async" | "default" | "auto" => k += 1,
+                "extern" => {
+                    let abi = self.nth(c, k + 1).map_or(false, |t| self.kind(t) == TokenKind::Str);
+                    let next = text(if abi { k + 2 } else { k + 1 });
+                    if next == Some("{") || next == Some("crate") {
+                        return Some((k, "extern"));
+                    }
+                    k += if abi { 2 } else { 1 };
+                },
+                "const" if !["fn", "unsafe", "async", "extern"].contains(&text(k + 1).unwrap_or("")) => {
+                    return Some((k, "const"));
+                },
+                "const" => k += 1,
+                "union" => {
+                    let named = self.nth(c, k + 1).map_or(false, |t| self.kind(t) == TokenKind::Ident);
+                    return if named { Some((k, "union")) } else { None };
+                },
+                s if ITEM_KEYWORDS.contains(&s) => return Some((k, s)),
+                _ => return None,
+            }
+        }
+    }
+
+    fn item(&mut self, c: &mut Cursor<'a>, keyword_index: usize, keyword: &str) -> Node {
+        let mut children = vec!();
+        for _ in 0..=keyword_index {
+            children.push(self.bump(c));
+        }
+        let kind = match keyword {
+            "fn" => SymbolKind::Fn,
+            "struct" => SymbolKind::Struct,
+            "enum" => SymbolKind::Enum,
+            "impl" => SymbolKind::Impl,
+            "mod" => SymbolKind::Mod,
+            _ => return self.item_rest(c, keyword, children),
+        };
+        let name = match kind {
+            SymbolKind::Impl => self.impl_name(c),
+            _ => self.nth(c, 0).filter(|t| self.kind(t) == TokenKind::Ident).map_or(String::new(), |t| self.text(t).to_string()),
+        };
+        if name.is_empty() || self.macro_depth > 0 {
+            return self.item_rest(c, keyword, children);
+        }
+        self.symbols.push(Symbol { kind, name, start: children[0].start, depth: self.depth });
+        self.depth += 1;
+        let item = self.item_rest(c, keyword, children);
+        self.depth -= 1;
+        item
+    }
+
+    // the header of an impl without its generic parameters, e.g. `Display for Foo<T>`
+    fn impl_name(&self, c: &Cursor<'a>) -> String {
+        let header = c.trees[c.pos..].iter()
+            .filter(|t| !self.is_comment(t))
+            .take_while(|t| !["where", "{", ";"].contains(&self.text(t)));
+        let mut name = String::new();
+        let mut depth = 0;
+        for (k, tree) in header.enumerate() {
+            let text = self.tree_text(tree);
+            if (k == 0 && text == "<") || depth > 0 {
+                depth += angle_depth(&text);
+                continue;
+            }
+            let is_word = |s: &str| s.starts_with(|c: char| c.is_alphanumeric() || c == '_');
+            if is_word(&text) && name.ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '>') {
+                name.push(' ');
+            }
+            name.push_str(&text);
+        }
+        name
+    }
+
+    fn tree_text(&self, tree: &Tree) -> String {
+        match tree {
+            Tree::Token(i) => self.tokens[*i].1.clone(),
+            Tree::Group(open, trees, close) => {
+                let mut text = self.tokens[*open].1.clone();
+                for tree in trees {
+                    text.push_str(&self.tree_text(tree));
+                }
+                if let Some(close) = close {
+                    text.push_str(&self.tokens[*close].1);
+                }
+                text
+            },
+        }
+    }
+
+    // the rest of an item after its keyword
+    fn item_rest(&mut self, c: &mut Cursor<'a>, keyword: &str, mut children: Vec<Node>) -> Node {
+        match keyword {
+            "const" | "static" | "type" | "use" => {
+                children.extend(self.raw_until(c, &["=", ";"]));
+                if self.peek_is(c, "=") {
+                    children.push(self.bump(c));
+                    if keyword == "type" {
+                        children.extend(element(self.raw_until(c, &[";"])));
+                    } else {
+                        children.extend(self.expression(c, false));
+                    }
+                }
+            },
+            _ => {
+                // the header, e.g. the name, generics, parameters and return type of a fn
+                while self.peek_text(c).map_or(false, |t| t != ";" && t != "{") {
+                    children.push(self.bump(c));
+                }
+                if self.peek_is(c, "{") {
+                    let body = match keyword {
+                        "fn" | "mod" | "trait" | "impl" | "extern" => {
+                            self.group_if(c, "{", Parser::block_contents).unwrap()
+                        },
+                        // fields, variants or the rules of a macro
+                        _ => self.bump(c),
+                    };
+                    children.push(body);
+                    return node(NodeKind::Item, children);
+                }
+            },
+        }
+        if self.peek_is(c, ";") {
+            children.push(self.bump(c));
+        }
+        node(NodeKind::Item, children)
+    }
+
+    // statements and items, comments between them are nodes of their own
+    fn block_contents(&mut self, c: &mut Cursor<'a>) -> Vec<Node> {
+        let mut nodes = vec!();
+        while let Some(tree) = c.trees.get(c.pos) {
+            if self.is_comment(tree) {
+                nodes.push(self.raw(tree));
+                c.pos += 1;
+            } else {
+                nodes.push(self.statement(c));
+            }
+        }
+        nodes
+    }
+
+    fn statement(&mut self, c: &mut Cursor<'a>) -> Node {
+        if let Some((index, keyword)) = self.item_keyword(c) {
+            return self.item(c, index, keyword);
+        }
+        let mut children = vec!();
+        while self.peek_is(c, "#") {
+            children.push(self.bump(c));
+            if self.peek_is(c, "[") {
+                children.push(self.bump(c));
+            }
+        }
+        let mut complete = false;
+        if self.peek_is(c, "let") {
+            children.extend(self.let_statement(c));
+        } else if let Some((expression, block_like)) = self.statement_expression(c) {
+            children.push(expression);
+            complete = block_like;
+        }
+        if !complete || self.peek_is(c, ";") {
+            // up to the `;`, including whatever isn't understood, e.g. while typing
+            while let Some(text) = self.peek_text(c) {
+                if !children.is_empty() && (text == "let" || self.item_keyword(c).is_some()) {
+                    break;
+                }
+                children.push(self.bump(c));
+                if text == ";" {
+                    break;
+                }
+            }
+        }
+        node(NodeKind::Statement, children)
+    }
+
+    // `let pattern: Type = expression else { ... }` without the `;`
+    fn let_statement(&mut self, c: &mut Cursor<'a>) -> Vec<Node> {
+        let mut children = vec!(self.bump(c));
+        children.extend(element(self.raw_until(c, &[":", "=", ";"])));
+        if self.peek_is(c, ":") {
+            children.push(self.bump(c));
+            children.extend(element(self.raw_until(c, &["=", ";"])));
+        }
+        if self.peek_is(c, "=") {
+            children.push(self.bump(c));
+            children.extend(self.expression(c, false));
+        }
+        if self.peek_is(c, "else") {
+            children.push(self.bump(c));
+            children.extend(self.group_if(c, "{", Parser::block_contents));
+        }
+        children
+    }
+
+    // the expression of a statement, and whether it's block-like, e.g. `if a {}`, so that the
+    // statement ends without a `;`
+    fn statement_expression(&mut self, c: &mut Cursor<'a>) -> Option<(Node, bool)> {
+        let tree = self.peek(c)?;
+        let next = self.nth_text(c, 1);
+        let block_like = match (self.kind(tree), self.text(tree)) {
+            (_, "if") | (_, "match") | (_, "loop") | (_, "while") | (_, "for") | (_, "{") => true,
+            (_, "unsafe") | (TokenKind::Macro, _) => next == Some("{"),
+            (TokenKind::Lifetime, _) => next == Some(":"),
+            _ => false,
+        };
+        if !block_like {
+            return self.expression(c, false).map(|e| (e, false));
+        }
+        let e = self.primary(c, false)?;
+        if !self.peek_is(c, ".") && !self.peek_is(c, "?") {
+            return Some((e, true));
+        }
+        let e = self.postfix(c, e);
+        Some((self.binary_rest(c, e, 0, false), false))
+    }
+
+    // `no_struct` in conditions like `if x {}`, where `{` starts the block rather than a struct
+    fn expression(&mut self, c: &mut Cursor<'a>, no_struct: bool) -> Option<Node> {
+        self.binary(c, 0, no_struct)
+    }
+
+    fn binary(&mut self, c: &mut Cursor<'a>, min_precedence: u8, no_struct: bool) -> Option<Node> {
+        let lhs = self.unary(c, no_struct)?;
+        Some(self.binary_rest(c, lhs, min_precedence, no_struct))
+    }
+
+    // the binary operators binding at least as tight as `min_precedence` following `lhs`
+    fn binary_rest(&mut self, c: &mut Cursor<'a>, mut lhs: Node, min_precedence: u8, no_struct: bool) -> Node {
+        loop {
+            let op = match self.peek(c) {
+                Some(Tree::Token(i)) => self.tokens[*i].1.as_str(),
+                _ => return lhs,
+            };
+            if op == "as" {
+                if AS_PRECEDENCE < min_precedence {
+                    return lhs;
+                }
+                let mut children = vec!(lhs, self.bump(c));
+                children.extend(self.cast_type(c));
+                lhs = node(NodeKind::Expression, children);
+                continue;
+            }
+            let (precedence, right_associative) = match binary_precedence(op) {
+                Some(p) if p.0 >= min_precedence => p,
+                _ => return lhs,
+            };
+            let mut children = vec!(lhs, self.bump(c));
+            // ranges can be open, e.g. the `0..` of `for i in 0.. {}`
+            if !(op.starts_with("..") && no_struct && self.peek_is(c, "{")) {
+                let min = if right_associative { precedence } else { precedence + 1 };
+                children.extend(self.binary(c, min, no_struct));
+            }
+            lhs = node(NodeKind::Expression, children);
+        }
+    }
+
+    fn unary(&mut self, c: &mut Cursor<'a>, no_struct: bool) -> Option<Node> {
+        let op = self.peek_text(c)?;
+        match op {
+            "-" | "!" | "*" | "&" | "&&" => {
+                let mut children = vec!(self.bump(c));
+                if op.starts_with('&') && self.peek_is(c, "mut") {
+                    children.push(self.bump(c));
+                }
+                children.extend(self.unary(c, no_struct));
+                Some(node(NodeKind::Expression, children))
+            },
+            ".." | "..=" => {
+                let mut children = vec!(self.bump(c));
+                if !(no_struct && self.peek_is(c, "{")) {
+                    children.extend(self.binary(c, RANGE_PRECEDENCE + 1, no_struct));
+                }
+                Some(node(NodeKind::Expression, children))
+            },
+            _ => {
+                let e = self.primary(c, no_struct)?;
+                Some(self.postfix(c, e))
+            },
+        }
+    }
+
+    // calls, indexing, fields, method calls, `?` and `.await` following `e`
+    fn postfix(&mut self, c: &mut Cursor<'a>, mut e: Node) -> Node {
+        loop {
+            let mut children = vec!(e);
+            match self.peek_text(c) {
+                Some("?") => children.push(self.bump(c)),
+                Some("(") | Some("[") => {
+                    let tree = self.next(c).unwrap();
+                    children.push(self.group(tree, Parser::expressions));
+                },
+                Some(".") => {
+                    children.push(self.bump(c));
+                    match (self.peek_kind(c), self.peek_text(c)) {
+                        (Some(TokenKind::Ident), _) | (Some(TokenKind::Number), _) | (_, Some("await")) => {
+                            children.push(self.bump(c));
+                        },
+                        _ => {},
+                    }
+                    if self.peek_is(c, "::") {
+                        children.push(self.bump(c));
+                        if self.peek_is(c, "<") {
+                            self.generics(c, &mut children);
+                        }
+                    }
+                    children.extend(self.group_if(c, "(", Parser::expressions));
+                },
+                _ => return children.pop().unwrap(),
+            }
+            e = node(NodeKind::Expression, children);
+        }
+    }
+
+    fn primary(&mut self, c: &mut Cursor<'a>, no_struct: bool) -> Option<Node> {
+        let tree = self.peek(c)?;
+        let text = self.text(tree);
+        match self.kind(tree) {
+            TokenKind::Number | TokenKind::Str | TokenKind::Char => return Some(self.bump(c)),
+            TokenKind::Ident => return Some(self.path(c, no_struct)),
+            TokenKind::Macro => return Some(self.macro_call(c)),
+            TokenKind::Lifetime if self.nth_text(c, 1) == Some(":") => {
+                // a label like `'outer: loop {}`
+                let mut children = vec!(self.bump(c), self.bump(c));
+                children.extend(self.primary(c, no_struct));
+                return Some(node(NodeKind::Expression, children));
+            },
+            _ => {},
+        }
+        let mut children = vec!();
+        match text {
+            "(" | "[" => {
+                c.pos += 1;
+                return Some(self.group(tree, Parser::expressions));
+            },
+            "{" => return self.group_if(c, "{", Parser::block_contents),
+            "::" | "<" => return Some(self.path(c, no_struct)),
+            s if PATH_KEYWORDS.contains(&s) => return Some(self.path(c, no_struct)),
+            "true" | "false" => return Some(self.bump(c)),
+            "if" => return Some(self.if_expression(c)),
+            "move" | "|" | "||" => return Some(self.closure(c, no_struct)),
+            "match" => {
+                children.push(self.bump(c));
+                children.extend(self.expression(c, true));
+                children.extend(self.group_if(c, "{", Parser::match_arms));
+            },
+            "while" => {
+                children.push(self.bump(c));
+                children.extend(self.expression(c, true));
+                children.extend(self.group_if(c, "{", Parser::block_contents));
+            },
+            "for" => {
+                children.push(self.bump(c));
+                children.extend(element(self.raw_until(c, &["in", "{"])));
+                if self.peek_is(c, "in") {
+                    children.push(self.bump(c));
+                    children.extend(self.expression(c, true));
+                }
+                children.extend(self.group_if(c, "{", Parser::block_contents));
+            },
+            "loop" | "unsafe" | "async" => {
+                children.push(self.bump(c));
+                if self.peek_is(c, "move") {
+                    children.push(self.bump(c));
+                }
+                children.extend(self.group_if(c, "{", Parser::block_contents));
+            },
+            "let" => {
+                // in conditions like `if let Some(x) = y && z`
+                children.push(self.bump(c));
+                children.extend(element(self.raw_until(c, &["=", "{"])));
+                if self.peek_is(c, "=") {
+                    children.push(self.bump(c));
+                    children.extend(self.binary(c, COMPARISON_PRECEDENCE, no_struct));
+                }
+            },
+            "return" | "break" | "continue" => {
+                children.push(self.bump(c));
+                if self.peek_kind(c) == Some(TokenKind::Lifetime) {
+                    children.push(self.bump(c));
+                }
+                children.extend(self.expression(c, no_struct));
+            },
+            _ => return None,
+        }
+        Some(node(NodeKind::Expression, children))
+    }
+
+    // a path like `::std::mem::swap` or `Vec::<u8>::new`, maybe of a struct expression like
+    // `A { x: 1 }`
+    fn path(&mut self, c: &mut Cursor<'a>, no_struct: bool) -> Node {
+        let mut children = vec!();
+        let mut last = "";
+        let mut segment = true;
+        while let Some(text) = self.peek_text(c) {
+            if text == "::" {
+                children.push(self.bump(c));
+                segment = true;
+                continue;
+            }
+            if !segment {
+                break;
+            }
+            segment = false;
+            if text == "<" {
+                self.generics(c, &mut children);
+            } else if self.peek_kind(c) == Some(TokenKind::Macro) {
+                children.push(self.macro_call(c));
+                return node(NodeKind::Expression, children);
+            } else if self.peek_kind(c) == Some(TokenKind::Ident) || PATH_KEYWORDS.contains(&text) {
+                children.push(self.bump(c));
+                last = text;
+            } else {
+                break;
+            }
+        }
+        // lowercase paths followed by `{` are more likely a missing `;` while typing
+        if !no_struct && last.starts_with(char::is_uppercase) {
+            children.extend(self.group_if(c, "{", Parser::fields));
+        }
+        node(NodeKind::Expression, children)
+    }
+
+    // a macro call, its arguments are parsed as expressions unless they're in braces
+    fn macro_call(&mut self, c: &mut Cursor<'a>) -> Node {
+        let mut children = vec!(self.bump(c));
+        match self.peek_text(c) {
+            Some("(") | Some("[") => {
+                let tree = self.next(c).unwrap();
+                self.macro_depth += 1;
+                children.push(self.group(tree, Parser::expressions));
+                self.macro_depth -= 1;
+            },
+            Some("{") => children.push(self.bump(c)),
+            _ => {},
+        }
+        node(NodeKind::Expression, children)
+    }
+
+    fn closure(&mut self, c: &mut Cursor<'a>, no_struct: bool) -> Node {
+        let mut children = vec!();
+        if self.peek_is(c, "move") {
+            children.push(self.bump(c));
+        }
+        if self.peek_is(c, "||") {
+            children.push(self.bump(c));
+        } else if self.peek_is(c, "|") {
+            children.push(self.bump(c));
+            while let Some(text) = self.peek_text(c) {
+                if text == "|" {
+                    children.push(self.bump(c));
+                    break;
+                } else if text == "," {
+                    children.push(self.bump(c));
+                } else {
+                    children.extend(element(self.raw_until(c, &[",", "|"])));
+                }
+            }
+        }
+        if self.peek_is(c, "->") {
+            children.push(self.bump(c));
+            children.extend(element(self.raw_until(c, &["{"])));
+        }
+        children.extend(self.expression(c, no_struct));
+        node(NodeKind::Expression, children)
+    }
+
+    fn if_expression(&mut self, c: &mut Cursor<'a>) -> Node {
+        let mut children = vec!(self.bump(c));
+        children.extend(self.expression(c, true));
+        children.extend(self.group_if(c, "{", Parser::block_contents));
+        if self.peek_is(c, "else") {
+            children.push(self.bump(c));
+            if self.peek_is(c, "if") {
+                children.push(self.if_expression(c));
+            } else {
+                children.extend(self.group_if(c, "{", Parser::block_contents));
+            }
+        }
+        node(NodeKind::Expression, children)
+    }
+
+    // `pattern if guard => expression,`
+    fn match_arms(&mut self, c: &mut Cursor<'a>) -> Vec<Node> {
+        let mut arms = vec!();
+        while let Some(tree) = c.trees.get(c.pos) {
+            if self.is_comment(tree) {
+                arms.push(self.raw(tree));
+                c.pos += 1;
+                continue;
+            }
+            let mut children = vec!();
+            children.extend(element(self.raw_until(c, &["if", "=>", ","])));
+            if self.peek_is(c, "if") {
+                children.push(self.bump(c));
+                children.extend(self.expression(c, false));
+            }
+            let mut complete = false;
+            if self.peek_is(c, "=>") {
+                children.push(self.bump(c));
+                if let Some((expression, block_like)) = self.statement_expression(c) {
+                    children.push(expression);
+                    complete = block_like;
+                }
+            }
+            if !complete || self.peek_is(c, ",") {
+                while let Some(text) = self.peek_text(c) {
+                    children.push(self.bump(c));
+                    if text == "," {
+                        break;
+                    }
+                }
+            }
+            arms.push(node(NodeKind::Element, children));
+        }
+        arms
+    }
+}
+
+const ITEM_KEYWORDS: &[&str] = &[
+    "fn", "struct", "enum", "trait", "impl", "mod", "type", "use", "static", "macro_rules!",
+];
+
+const PATH_KEYWORDS: &[&str] = &["self", "Self", "crate", "super"];
+
+const RANGE_PRECEDENCE: u8 = 2;
+const COMPARISON_PRECEDENCE: u8 = 5;
+
+fn angle_depth(text: &str) -> i32 {
+    match text {
+        "<" => 1,
+        "<<" => 2,
+        ">" => -1,
+        ">>" => -2,
+        _ => 0,
+    }
+}
+
+fn element(children: Vec<Node>) -> Option<Node> {
+    if children.is_empty() {
+        None
+    } else {
+        Some(node(NodeKind::Element, children))
+    }
+}
+
+pub fn parse(content: &Content) -> Node {
+    parse_with_symbols(content).0
+}
+
+fn parse_with_symbols(content: &Content) -> (Node, Vec<Symbol>) {
+    let tokens: Vec<(Token, String)> = content.tokens().into_iter()
+        .map(|t| {
+            let s = content.chars_of(t.start, t.end);
+            (t, s)
+        })
+        .collect();
+    let mut trees = vec!();
+    let mut pos = 0;
+    loop {
+        trees.extend(token_trees(&tokens, &mut pos));
+        if pos == tokens.len() {
+            break;
+        }
+        // a stray closing bracket
+        trees.push(Tree::Token(pos));
+        pos += 1;
+    }
+    let mut parser = Parser { tokens: &tokens, symbols: vec!(), depth: 0, macro_depth: 0 };
+    let children = parser.block_contents(&mut Cursor { trees: &trees, pos: 0 });
+    (Node { kind: NodeKind::File, start: 0, end: content.num_elmts(), children }, parser.symbols)
+}
+
+impl Node {
+    pub fn contains(&self, start: usize, end: usize) -> bool {
+        self.start <= start && end <= self.end
+    }
+
+    // all nodes containing the range, outermost first
+    pub fn path_to(&self, start: usize, end: usize) -> Vec<&Node> {
+        let mut path = vec!();
+        let mut node = self;
+        while node.contains(start, end) {
+            path.push(node);
+            match node.children.iter().find(|c| c.contains(start, end)) {
+                Some(child) => node = child,
+                None => break,
+            }
+        }
+        path
+    }
+
+    // the range of the smallest node that's bigger than the given range
+    pub fn expand(&self, start: usize, end: usize) -> Option<(usize, usize)> {
+        self.path_to(start, end).into_iter().rev()
+            .map(|n| (n.start, n.end))
+            .find(|r| *r != (start, end))
+    }
+
+    // the outermost node (except the file) that starts with the token at elmt `i`
+    fn node_at(&self, i: usize) -> Option<(Vec<&Node>, usize)> {
+        let path = self.path_to(i, i + 1);
+        let depth = path.iter().position(|n| n.kind != NodeKind::File && n.start == i)
+            .or_else(|| if path.len() > 1 { Some(path.len() - 1) } else { None })?;
+        Some((path, depth))
+    }
+
+    pub fn parent_start(&self, i: usize) -> Option<usize> {
+        let (path, depth) = self.node_at(i)?;
+        path[..depth].iter().rev()
+            .find(|n| n.kind != NodeKind::File)
+            .map(|n| n.start)
+    }
+
+    pub fn sibling_start(&self, i: usize, next: bool) -> Option<usize> {
+        let (path, depth) = self.node_at(i)?;
+        let node = path[depth];
+        let siblings = &path[depth - 1].children;
+        let index = siblings.iter().position(|n| n == node)?;
+        let sibling = if next { siblings.get(index + 1) } else { index.checked_sub(1).map(|i| &siblings[i]) };
+        sibling.map(|n| n.start)
+    }
+}
+
+// Expanding and shrinking of a selection along the syntax tree
+#[derive(Clone, Debug, Default)]
+pub struct StructuralSelection {
+    stack: Vec<(usize, usize)>,
+}
+
+impl StructuralSelection {
+    pub fn current(&self) -> Option<(usize, usize)> {
+        self.stack.last().cloned()
+    }
+
+    pub fn expand(&mut self, content: &Content) {
+        let tree = parse(content);
+        let next = match self.current() {
+            Some((start, end)) => tree.expand(start, end),
+            None => {
+                let i = content.elmt_at_cursor();
+                tree.path_to(i, i + 1).last()
+                    .filter(|n| n.kind == NodeKind::Token)
+                    .map(|n| (n.start, n.end))
+                    .or_else(|| tree.expand(i, i))
+            },
+        };
+        if let Some(range) = next {
+            self.stack.push(range);
+        }
+    }
+
+    pub fn shrink(&mut self) {
+        self.stack.pop();
+    }
+
+    pub fn clear(&mut self) {
+        self.stack.clear();
+    }
+}
+
+pub fn jump_to_parent(content: &mut Content) {
+    let i = content.elmt_at_cursor();
+    if let Some(start) = parse(content).parent_start(i) {
+        content.set_cursor(content.position_before_char(start));
+    }
+}
+
+pub fn jump_to_sibling(content: &mut Content, next: bool) {
+    let i = content.elmt_at_cursor();
+    if let Some(start) = parse(content).sibling_start(i, next) {
+        content.set_cursor(content.position_before_char(start));
+    }
+}
+
+// folds the `{}` bodies of all top-level items
+pub fn fold_items(content: &mut Content) {
+    let tree = parse(content);
+    let bodies: Vec<usize> = tree.children.iter()
+        .filter(|n| n.kind == NodeKind::Item)
+        .filter_map(|item| {
+            item.children.iter().rev().find(|n| n.kind == NodeKind::Group && content.char_at(n.start) == '{')
+        })
+        .map(|body| body.start)
+        .collect();
+    content.fold_blocks(&bodies);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Fn,
+    Struct,
+    Enum,
+    Impl,
+    Mod,
+}
+
+impl SymbolKind {
+    pub fn keyword(self) -> &'static str {
+        match self {
+            SymbolKind::Fn => "fn",
+            SymbolKind::Struct => "struct",
+            SymbolKind::Enum => "enum",
+            SymbolKind::Impl => "impl",
+            SymbolKind::Mod => "mod",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub kind: SymbolKind,
+    pub name: String,
+    pub start: usize,  // elmt index of the item
+    pub depth: usize,  // number of enclosing symbols
+}
+
+// the fns, structs, enums, impls and mods of the typed text, in order
+pub fn symbols(content: &Content) -> Vec<Symbol> {
+    parse_with_symbols(content).1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(content: &Content, range: (usize, usize)) -> String {
+        content.chars_of(range.0, range.1)
+    }
+
+    #[test]
+    fn test_parse_items() {
+        let c = Content::from_string("#[test] fn a() { let x = (1, 2); x } struct B;");
+        let tree = parse(&c);
+        let kinds: Vec<_> = tree.children.iter().map(|n| (n.kind, text(&c, (n.start, n.end)))).collect();
+        assert_eq!(kinds, vec!(
+            (NodeKind::Item, "#[test]fna(){letx=(1,2);x}".to_string()),
+            (NodeKind::Item, "structB;".to_string()),
+        ));
+    }
+
+    #[test]
+    fn test_expand_selection() {
+        let mut c = Content::from_strings("fn a(){let x=foo(1+2, 3);}", "fn a() {\n    let x = foo(1 + 2, 3);\n}");
+        c.set_cursor(c.position_before_char(16));  // `+`
+        let mut sel = StructuralSelection::default();
+        let mut steps = vec!();
+        for _ in 0..7 {
+            sel.expand(&c);
+            steps.push(text(&c, sel.current().unwrap()));
+        }
+        assert_eq!(steps, vec!(
+            "+", "1+2", "(1+2,3)", "foo(1+2,3)", "letx=foo(1+2,3);", "{letx=foo(1+2,3);}",
+            "fna(){letx=foo(1+2,3);}",
+        ));
+        sel.shrink();
+        assert_eq!(text(&c, sel.current().unwrap()), "{letx=foo(1+2,3);}");
+    }
+
+    fn expansions(s: &str, i: usize, n: usize) -> Vec<String> {
+        let mut c = Content::from_string(s);
+        c.set_cursor(c.position_before_char(i));
+        let mut sel = StructuralSelection::default();
+        (0..n).map(|_| {
+            sel.expand(&c);
+            text(&c, sel.current().unwrap())
+        }).collect()
+    }
+
+    #[test]
+    fn test_expand_binary_expressions() {
+        assert_eq!(expansions("let x = a + b * c;", 7, 4), vec!("b", "b*c", "a+b*c", "letx=a+b*c;"));
+        assert_eq!(expansions("let x = a * b + c;", 7, 4), vec!("b", "a*b", "a*b+c", "letx=a*b+c;"));
+        assert_eq!(expansions("x = a || b && c == d - e;", 11, 6), vec!(
+            "d", "d-e", "c==d-e", "b&&c==d-e", "a||b&&c==d-e", "x=a||b&&c==d-e",
+        ));
+    }
+
+    #[test]
+    fn test_expand_postfix_expressions() {
+        assert_eq!(expansions("a = -b.c(d)? as u8 + e;", 5, 8), vec!(
+            "c", "b.c(d)", "b.c(d)?", "-b.c(d)?", "-b.c(d)?asu8", "-b.c(d)?asu8+e", "a=-b.c(d)?asu8+e",
+            "a=-b.c(d)?asu8+e;",
+        ));
+        assert_eq!(expansions("if !x.y[0] { z }", 5, 4), vec!("y", "x.y", "x.y[0]", "!x.y[0]"));
+    }
+
+    #[test]
+    fn test_parse_statements() {
+        let c = Content::from_string("fn a() { if b { c } d.e(); f! { g } 'h: loop {} i }");
+        let tree = parse(&c);
+        let body = tree.children[0].children.last().unwrap();
+        let statements: Vec<_> = body.children.iter()
+            .filter(|n| n.kind == NodeKind::Statement)
+            .map(|n| text(&c, (n.start, n.end)))
+            .collect();
+        assert_eq!(statements, vec!("ifb{c}", "d.e();", "f!{g}", "'h:loop{}", "i"));
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut c = Content::from_string("fn a() { x; y; }\nfn b() {}");
+        c.set_cursor(c.position_before_char(8));  // `y`
+        jump_to_sibling(&mut c, false);
+        assert_eq!(c.char_at(c.elmt_at_cursor()), 'x');
+        jump_to_parent(&mut c);
+        assert_eq!(c.char_at(c.elmt_at_cursor()), '{');
+        jump_to_parent(&mut c);
+        assert_eq!(c.elmt_at_cursor(), 0);
+        jump_to_sibling(&mut c, true);
+        assert_eq!(text(&c, (c.elmt_at_cursor(), c.elmt_at_cursor() + 4)), "fnb(");
+    }
+
+    #[test]
+    fn test_symbols() {
+        let c = Content::from_string("mod m { pub struct A; enum B { X } } impl<T> Display for Foo<T> { fn fmt(&self) {} } pub(crate) fn main() {}");
+        let found: Vec<_> = symbols(&c).into_iter().map(|s| (s.kind.keyword(), s.name, s.depth)).collect();
+        assert_eq!(found, vec!(
+            ("mod", "m".to_string(), 0),
+            ("struct", "A".to_string(), 1),
+            ("enum", "B".to_string(), 1),
+            ("impl", "Display for Foo<T>".to_string(), 0),
+            ("fn", "fmt".to_string(), 1),
+            ("fn", "main".to_string(), 0),
+        ));
+    }
+
+    #[test]
+    fn test_nested_symbols() {
+        let c = Content::from_string(concat!(
+            "mod a { mod b { fn c() { fn d() {} } } } ",
+            "unsafe impl<T: Into<u8>, F: Fn() -> T> Send for X<T, F> where T: Copy {} ",
+            "macro_rules! m { () => { fn hidden() {} } } ",
+            "fn e() { quote!(struct Hidden;); m! { enum Hidden {} } let s = \"fn hidden\"; }",
+        ));
+        let found: Vec<_> = symbols(&c).into_iter().map(|s| (s.kind.keyword(), s.name, s.depth)).collect();
+        assert_eq!(found, vec!(
+            ("mod", "a".to_string(), 0),
+            ("mod", "b".to_string(), 1),
+            ("fn", "c".to_string(), 2),
+            ("fn", "d".to_string(), 3),
+            ("impl", "Send for X<T,F>".to_string(), 0),
+            ("fn", "e".to_string(), 0),
+        ));
+    }
+
+    #[test]
+    fn test_fold_items() {
+        let mut c = Content::from_string("fn a() {\n    1\n}\nstruct B;\nimpl C {\n    fn d() {}\n}");
+        fold_items(&mut c);
+        assert_eq!(&c.display_string(), "fn a() {⋯}\nstruct B;\nimpl C {⋯}");
+    }
+}
@@ -0,0 +1,274 @@
+// Terminal front-end. `Tui` keeps the editor state and renders it into a `Screen`, a grid of
+// styled cells, so it can be driven and checked without a terminal. `src/bin/tui.rs` draws
+// the screen with crossterm.
+//
+// The code is formatted to the width of the terminal. Virtual spaces are shown as a dim `·`
+// and virtual line breaks get a dim `↵`, so the typed text can be told from the layout.
+
+use crate::content::{Content, WhitespaceDiff};
+use crate::keymap::{Command, KeyChord, Keymap};
+use crate::lexer::TokenKind;
+
+pub const VIRTUAL_SPACE: char = '·';
+pub const VIRTUAL_NEWLINE: char = '↵';
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    Plain,
+    Token(TokenKind),
+    Virtual,
+    StatusBar,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub c: char,
+    pub style: Style,
+}
+
+const EMPTY: Cell = Cell { c: ' ', style: Style::Plain };
+
+// a virtual terminal
+pub struct Screen {
+    pub width: usize,
+    pub height: usize,
+    cells: Vec<Cell>,
+    pub cursor: Option<(usize, usize)>,  // row and column
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Screen {
+        Screen { width, height, cells: vec![EMPTY; width * height], cursor: None }
+    }
+
+    pub fn clear(&mut self) {
+        for cell in &mut self.cells {
+            *cell = EMPTY;
+        }
+        self.cursor = None;
+    }
+
+    // cells outside of the screen are dropped
+    pub fn set(&mut self, row: usize, col: usize, c: char, style: Style) {
+        if row < self.height && col < self.width {
+            self.cells[row * self.width + col] = Cell { c, style };
+        }
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> Cell {
+        self.cells[row * self.width + col]
+    }
+
+    pub fn row_text(&self, row: usize) -> String {
+        let text: String = self.cells[row * self.width..(row + 1) * self.width].iter().map(|cell| cell.c).collect();
+        text.trim_end().to_string()
+    }
+}
+
+pub struct Tui {
+    content: Content,
+    keymap: Keymap,
+    width: usize,
+    height: usize,
+    scroll: usize,  // first displayed line
+    status: String,
+    quit: bool,
+}
+
+impl Tui {
+    pub fn new(text: &str, width: usize, height: usize) -> Tui {
+        let mut tui = Tui {
+            content: Content::from_string(text),
+            keymap: Keymap::default(),
+            width,
+            height,
+            scroll: 0,
+            status: String::new(),
+            quit: false,
+        };
+        tui.format();
+        tui
+    }
+
+    pub fn content(&self) -> &Content {
+        &self.content
+    }
+
+    pub fn set_status(&mut self, status: &str) {
+        self.status = status.to_string();
+    }
+
+    pub fn quit(&self) -> bool {
+        self.quit
+    }
+
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.format();
+        self.scroll_to_cursor();
+    }
+
+    // the last line is the status bar
+    fn code_height(&self) -> usize {
+        self.height.saturating_sub(1)
+    }
+
+    fn format(&mut self) {
+        self.status = match self.content.update_virtual_whitespace(self.width) {
+            Some(stats) => stats.to_string(),
+            None => "rustfmt can't format the code".to_string(),
+        };
+    }
+
+    pub fn handle_key(&mut self, chord: &KeyChord) {
+        if chord.ctrl && chord.key == "q" {
+            self.quit = true;
+            return;
+        }
+        if let Some(cmd) = self.keymap.command(chord) {
+            self.execute(cmd);
+        }
+        self.scroll_to_cursor();
+    }
+
+    fn execute(&mut self, cmd: Command) {
+        match cmd {
+            Command::CursorLeft => self.content.cursor_left(),
+            Command::CursorRight => self.content.cursor_right(),
+            Command::CursorUp => self.content.cursor_up(),
+            Command::CursorDown => self.content.cursor_down(),
+            Command::CursorHome => self.content.cursor_home(),
+            Command::CursorEnd => self.content.cursor_end(),
+            Command::CursorWordLeft => self.content.cursor_word_left(),
+            Command::CursorWordRight => self.content.cursor_word_right(),
+            Command::JumpToMatchingBracket => self.content.jump_to_matching_bracket(),
+            Command::Backspace => {
+                self.content.backspace_keeping_tokens();
+                self.format();
+            },
+            Command::Delete => {
+                self.content.delete_keeping_tokens();
+                self.format();
+            },
+            Command::DeleteWordBackward => {
+                self.content.delete_word_backward();
+                self.format();
+            },
+            Command::DeleteWordForward => {
+                self.content.delete_word_forward();
+                self.format();
+            },
+            Command::Insert(c) => {
+                self.content.insert_auto_close(c);
+                self.format();
+            },
+            Command::Format => self.format(),
+            Command::ClearVirtualWhitespace => self.content.clear_virtual_whitespace(),
+            other => self.status = format!("{:?} isn't available in the terminal", other),
+        }
+    }
+
+    fn scroll_to_cursor(&mut self) {
+        let line = (self.content.cursor_pos_2().1).0;
+        if line < self.scroll {
+            self.scroll = line;
+        } else if line >= self.scroll + self.code_height() {
+            self.scroll = line + 1 - self.code_height();
+        }
+    }
+
+    pub fn render(&self, screen: &mut Screen) {
+        screen.clear();
+        let kinds: Vec<Option<TokenKind>> = self.content.highlight().into_iter()
+            .flat_map(|(kind, s)| s.chars().map(|_| kind).collect::<Vec<_>>())
+            .collect();
+        let chars = self.content.whitespace_diff().into_iter()
+            .flat_map(|(diff, s)| s.chars().map(|c| (diff, c)).collect::<Vec<_>>());
+
+        let (mut line, mut col) = (0, 0);
+        for ((diff, c), kind) in chars.zip(kinds) {
+            let visible = line >= self.scroll && line < self.scroll + self.code_height();
+            let row = line.wrapping_sub(self.scroll);
+            if c == '\n' {
+                if visible && diff == WhitespaceDiff::Supplemented {
+                    screen.set(row, col, VIRTUAL_NEWLINE, Style::Virtual);
+                }
+                line += 1;
+                col = 0;
+                continue;
+            }
+            let (c, style) = match (diff, kind) {
+                (WhitespaceDiff::Supplemented, _) => (VIRTUAL_SPACE, Style::Virtual),
+                (_, Some(kind)) => (c, Style::Token(kind)),
+                _ => (c, Style::Plain),
+            };
+            if visible {
+                screen.set(row, col, c, style);
+            }
+            col += 1;
+        }
+
+        let (line, col) = self.content.cursor_pos_2().1;
+        if line >= self.scroll && line < self.scroll + self.code_height() {
+            screen.cursor = Some((line - self.scroll, col));
+        }
+        let status = format!(" {}:{}  {}", line + 1, col + 1, self.status);
+        let status_row = self.height.saturating_sub(1);
+        let mut status_chars = status.chars();
+        for col in 0..self.width {
+            screen.set(status_row, col, status_chars.next().unwrap_or(' '), Style::StatusBar);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rendered(tui: &Tui) -> Screen {
+        let mut screen = Screen::new(tui.width, tui.height);
+        tui.render(&mut screen);
+        screen
+    }
+
+    #[test]
+    fn test_render() {
+        let tui = Tui::new("fn a(){1}", 20, 5);
+        let screen = rendered(&tui);
+        assert_eq!(&screen.row_text(0), "fn a()·{↵");
+        assert_eq!(&screen.row_text(1), "····1↵");
+        assert_eq!(&screen.row_text(2), "}↵");
+        assert_eq!(screen.cell(0, 0).style, Style::Token(TokenKind::Keyword));
+        assert_eq!(screen.cell(0, 6).style, Style::Virtual);
+        assert_eq!(screen.cell(4, 0).style, Style::StatusBar);
+        assert!(screen.row_text(4).starts_with(" 1:1"));
+        assert_eq!(screen.cursor, Some((0, 0)));
+    }
+
+    #[test]
+    fn test_keys_and_scrolling() {
+        let mut tui = Tui::new("", 30, 2);
+        for key in "fn a(){".chars() {
+            tui.handle_key(&KeyChord::plain(&key.to_string()));
+        }
+        tui.handle_key(&KeyChord::plain("Enter"));
+        tui.handle_key(&KeyChord::plain("x"));
+        assert_eq!(&tui.content().typed_string(), "fn a(){\nx}");
+        let screen = rendered(&tui);
+        // there's room for one line of code, the first one is scrolled out
+        assert_eq!(&screen.row_text(0), "····x↵");
+        assert_eq!(screen.cursor, Some((0, 5)));
+
+        tui.handle_key(&"Ctrl+q".parse().unwrap());
+        assert!(tui.quit());
+    }
+
+    #[test]
+    fn test_resize() {
+        let mut tui = Tui::new("fn a(x:u8,y:u8){}", 80, 5);
+        assert_eq!(&rendered(&tui).row_text(0), "fn a(x:·u8,·y:·u8)·{}↵");
+        tui.resize(12, 8);
+        assert_eq!(&rendered(&tui).row_text(0), "fn a(↵");
+    }
+}
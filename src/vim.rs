@@ -0,0 +1,761 @@
+// Modal (Vim-style) editing on top of `Content`.
+//
+// Positions are stored as typed offsets (see `Content::typed_offset`) so that they stay
+// valid when the virtual whitespace is updated in between two keys.
+
+use std::collections::HashMap;
+
+use crate::content::{Content, GetString, WordStop};
+use crate::keymap::KeyChord;
+
+const ESCAPE: char = '\u{1b}';
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    NotHandled,
+    Handled,
+    Edited,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Register {
+    pub text: String,
+    pub linewise: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    Down,
+    Up,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    FirstLine,
+    LastLine,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action {
+    Move(Motion),
+    Operate(Operator, Motion),
+    OperateLines(Operator),
+    DeleteChar,
+    DeleteToEnd,
+    ChangeToEnd,
+    Paste { before: bool },
+    Insert,
+    Append,
+    InsertAtLineStart,
+    AppendAtLineEnd,
+    OpenLine { above: bool },
+    Visual,
+    Repeat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Cmd {
+    register: Option<char>,
+    count: Option<usize>,
+    action: Action,
+}
+
+enum Parse {
+    Incomplete,
+    Invalid,
+    Done(Cmd),
+}
+
+impl Motion {
+    fn is_linewise(self) -> bool {
+        match self {
+            Motion::Down | Motion::Up | Motion::FirstLine | Motion::LastLine => true,
+            _ => false,
+        }
+    }
+
+    fn is_inclusive(self) -> bool {
+        self == Motion::WordEnd
+    }
+}
+
+impl Action {
+    fn is_change(self) -> bool {
+        match self {
+            Action::Move(_) | Action::Operate(Operator::Yank, _) | Action::OperateLines(Operator::Yank)
+                | Action::Visual | Action::Repeat => false,
+            _ => true,
+        }
+    }
+}
+
+pub struct Vim {
+    mode: Mode,
+    pending: Vec<KeyChord>,
+    registers: HashMap<char, Register>,
+    last_change: Vec<KeyChord>,
+    recording: Option<Vec<KeyChord>>,  // keys of a change that's still in insert mode
+    visual_anchor: usize,
+}
+
+impl Default for Vim {
+    fn default() -> Self {
+        Vim {
+            mode: Mode::Normal,
+            pending: vec!(),
+            registers: HashMap::new(),
+            last_change: vec!(),
+            recording: None,
+            visual_anchor: 0,
+        }
+    }
+}
+
+// the char a key stands for in normal and visual mode. Keys that edit in insert mode get
+// their Vim meaning, so they don't fall through to the keymap.
+fn key_char(key: &KeyChord) -> Option<char> {
+    match key.key.as_ref() {
+        "Escape" => Some(ESCAPE),
+        "Enter" => Some('j'),
+        "Backspace" => Some('h'),
+        "Delete" => Some('x'),
+        "ArrowLeft" => Some('h'),
+        "ArrowRight" => Some('l'),
+        "ArrowDown" => Some('j'),
+        "ArrowUp" => Some('k'),
+        "Home" => Some('0'),
+        "End" => Some('$'),
+        _ => key.typed_char(),
+    }
+}
+
+fn parse_count(keys: &[char], mut i: usize) -> (Option<usize>, usize) {
+    let start = i;
+    while i < keys.len() && keys[i].is_ascii_digit() && !(i == start && keys[i] == '0') {
+        i += 1;
+    }
+    if i == start {
+        return (None, i);
+    }
+    // saturates instead of overflowing, counts are clamped to the text later anyway
+    let count = keys[start..i].iter()
+        .fold(0usize, |n, c| n.saturating_mul(10).saturating_add(c.to_digit(10).unwrap() as usize));
+    (Some(count), i)
+}
+
+// a count can't do more than there are chars in the text, e.g. `999999999l` stops at the end of
+// the text instead of trying to move the cursor that often
+fn clamp_count(count: usize, content: &Content) -> usize {
+    std::cmp::min(count, content.get_string().chars().count() + 1)
+}
+
+// parses the motion at `keys[i..]`
+fn parse_motion(keys: &[char], i: usize) -> Result<Motion, Parse> {
+    let motion = match keys.get(i) {
+        None => return Err(Parse::Incomplete),
+        Some('h') => Motion::Left,
+        Some('l') | Some(' ') => Motion::Right,
+        Some('j') => Motion::Down,
+        Some('k') => Motion::Up,
+        Some('w') => Motion::WordForward,
+        Some('b') => Motion::WordBackward,
+        Some('e') => Motion::WordEnd,
+        Some('0') => Motion::LineStart,
+        Some('$') => Motion::LineEnd,
+        Some('G') => Motion::LastLine,
+        Some('g') => match keys.get(i + 1) {
+            None => return Err(Parse::Incomplete),
+            Some('g') => Motion::FirstLine,
+            Some(_) => return Err(Parse::Invalid),
+        },
+        Some(_) => return Err(Parse::Invalid),
+    };
+    let len = if motion == Motion::FirstLine { 2 } else { 1 };
+    if keys.len() > i + len {
+        return Err(Parse::Invalid);
+    }
+    Ok(motion)
+}
+
+fn parse(keys: &[char]) -> Parse {
+    let mut i = 0;
+    let mut register = None;
+    if keys.first() == Some(&'"') {
+        match keys.get(1) {
+            None => return Parse::Incomplete,
+            Some(r) => register = Some(*r),
+        }
+        i = 2;
+    }
+    let (count, i) = parse_count(keys, i);
+    let done = |action| Parse::Done(Cmd { register, count, action });
+    let operator = match keys.get(i) {
+        None => return Parse::Incomplete,
+        Some('d') => Operator::Delete,
+        Some('c') => Operator::Change,
+        Some('y') => Operator::Yank,
+        Some(c) => {
+            let action = match c {
+                'x' => Action::DeleteChar,
+                'D' => Action::DeleteToEnd,
+                'C' => Action::ChangeToEnd,
+                'p' => Action::Paste { before: false },
+                'P' => Action::Paste { before: true },
+                'i' => Action::Insert,
+                'a' => Action::Append,
+                'I' => Action::InsertAtLineStart,
+                'A' => Action::AppendAtLineEnd,
+                'o' => Action::OpenLine { above: false },
+                'O' => Action::OpenLine { above: true },
+                'v' => Action::Visual,
+                '.' => Action::Repeat,
+                _ => return match parse_motion(keys, i) {
+                    Ok(motion) => done(Action::Move(motion)),
+                    Err(p) => p,
+                },
+            };
+            if keys.len() > i + 1 {
+                return Parse::Invalid;
+            }
+            return done(action);
+        },
+    };
+
+    // operators take an optional count and a motion, or are doubled (`dd`)
+    let op_char = keys[i];
+    let (motion_count, j) = parse_count(keys, i + 1);
+    let count = match (count, motion_count) {
+        (Some(a), Some(b)) => Some(a.saturating_mul(b)),
+        (a, b) => a.or(b),
+    };
+    let done = |action| Parse::Done(Cmd { register, count, action });
+    if keys.get(j) == Some(&op_char) {
+        return if keys.len() > j + 1 { Parse::Invalid } else { done(Action::OperateLines(operator)) };
+    }
+    match parse_motion(keys, j) {
+        // `cw` behaves like `ce`
+        Ok(Motion::WordForward) if operator == Operator::Change => done(Action::Operate(operator, Motion::WordEnd)),
+        Ok(motion) => done(Action::Operate(operator, motion)),
+        Err(p) => p,
+    }
+}
+
+impl Vim {
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn register(&self, name: char) -> Option<&Register> {
+        self.registers.get(&name)
+    }
+
+    // the keys of the command that's currently being typed
+    pub fn pending_keys(&self) -> String {
+        self.pending.iter().filter_map(key_char).collect()
+    }
+
+    // the selected typed text in visual mode, as cursor positions
+    pub fn selection(&self, content: &Content) -> Option<((usize, usize), (usize, usize))> {
+        if self.mode != Mode::Visual {
+            return None;
+        }
+        let (start, end) = self.visual_range(content);
+        Some((content.cursor_at_typed_offset(start), content.cursor_at_typed_offset(end)))
+    }
+
+    pub fn handle_key(&mut self, key: &KeyChord, content: &mut Content) -> Outcome {
+        if key.ctrl || key.alt || key.meta {
+            return Outcome::NotHandled;
+        }
+        match self.mode {
+            Mode::Insert => self.handle_insert_key(key, content),
+            Mode::Normal => self.handle_normal_key(key, content),
+            Mode::Visual => self.handle_visual_key(key, content),
+        }
+    }
+
+    fn handle_insert_key(&mut self, key: &KeyChord, content: &mut Content) -> Outcome {
+        if let Some(keys) = &mut self.recording {
+            keys.push(key.clone());
+        }
+        match key.key.as_ref() {
+            "Escape" => {
+                self.mode = Mode::Normal;
+                if let Some(keys) = self.recording.take() {
+                    self.last_change = keys;
+                }
+                content.cursor_left();
+                Outcome::Handled
+            },
+            "ArrowLeft" => { content.cursor_left(); Outcome::Handled },
+            "ArrowRight" => { content.cursor_right(); Outcome::Handled },
+            "ArrowUp" => { content.cursor_up(); Outcome::Handled },
+            "ArrowDown" => { content.cursor_down(); Outcome::Handled },
+            "Home" => { content.cursor_home(); Outcome::Handled },
+            "End" => { content.cursor_end(); Outcome::Handled },
+            "Backspace" => { content.backspace_keeping_tokens(); Outcome::Edited },
+            "Delete" => { content.delete_keeping_tokens(); Outcome::Edited },
+            "Enter" => { content.insert('\n'); Outcome::Edited },
+            _ => match key.typed_char() {
+                Some(c) => { content.insert(c); Outcome::Edited },
+                None => Outcome::NotHandled,
+            },
+        }
+    }
+
+    fn handle_visual_key(&mut self, key: &KeyChord, content: &mut Content) -> Outcome {
+        let c = match key_char(key) {
+            Some(c) => c,
+            None => return Outcome::NotHandled,
+        };
+        self.pending.push(key.clone());
+        let keys: Vec<char> = self.pending.iter().filter_map(key_char).collect();
+        let operator = match c {
+            ESCAPE | 'v' => {
+                self.pending.clear();
+                self.mode = Mode::Normal;
+                return Outcome::Handled;
+            },
+            'd' | 'x' => Operator::Delete,
+            'c' => Operator::Change,
+            'y' => Operator::Yank,
+            _ => {
+                let (count, i) = parse_count(&keys, 0);
+                return match parse_motion(&keys, i) {
+                    Err(Parse::Incomplete) => Outcome::Handled,
+                    Ok(motion) => {
+                        self.pending.clear();
+                        self.apply_motion(content, motion, clamp_count(count.unwrap_or(1), content));
+                        Outcome::Handled
+                    },
+                    Err(_) => {
+                        self.pending.clear();
+                        Outcome::Handled
+                    },
+                };
+            },
+        };
+        self.pending.clear();
+        let (start, end) = self.visual_range(content);
+        self.mode = Mode::Normal;
+        self.operate(content, operator, None, start, end, false)
+    }
+
+    fn handle_normal_key(&mut self, key: &KeyChord, content: &mut Content) -> Outcome {
+        if key_char(key).is_none() {
+            return Outcome::NotHandled;
+        }
+        self.pending.push(key.clone());
+        let keys: Vec<char> = self.pending.iter().filter_map(key_char).collect();
+        if keys.last() == Some(&ESCAPE) {
+            self.pending.clear();
+            return Outcome::Handled;
+        }
+        let cmd = match parse(&keys) {
+            Parse::Incomplete => return Outcome::Handled,
+            Parse::Invalid => {
+                self.pending.clear();
+                return Outcome::Handled;
+            },
+            Parse::Done(cmd) => cmd,
+        };
+        let keys = std::mem::replace(&mut self.pending, vec!());
+        if cmd.action.is_change() {
+            self.recording = Some(keys);
+        }
+        let outcome = self.execute(cmd, content);
+        // changes that don't end in insert mode are complete now
+        if self.mode != Mode::Insert {
+            if let Some(keys) = self.recording.take() {
+                self.last_change = keys;
+            }
+        }
+        outcome
+    }
+
+    fn execute(&mut self, cmd: Cmd, content: &mut Content) -> Outcome {
+        let count = clamp_count(cmd.count.unwrap_or(1), content);
+        let offset = content.typed_offset(content.cursor());
+        match cmd.action {
+            Action::Move(motion) => {
+                if motion == Motion::LastLine || motion == Motion::FirstLine {
+                    self.apply_line_motion(content, motion, cmd.count);
+                } else {
+                    self.apply_motion(content, motion, count);
+                }
+                Outcome::Handled
+            },
+            Action::Operate(operator, motion) => {
+                let mut target = content.clone();
+                if motion.is_linewise() {
+                    let line = content.cursor_pos().0 .0;
+                    if motion == Motion::LastLine || motion == Motion::FirstLine {
+                        self.apply_line_motion(&mut target, motion, cmd.count);
+                    } else {
+                        self.apply_motion(&mut target, motion, count);
+                    }
+                    let target_line = target.cursor_pos().0 .0;
+                    let lines = (std::cmp::min(line, target_line), std::cmp::max(line, target_line));
+                    return self.operate_lines(content, operator, cmd.register, lines);
+                }
+                self.apply_motion(&mut target, motion, count);
+                let target_offset = target.typed_offset(target.cursor());
+                let (start, mut end) = (std::cmp::min(offset, target_offset), std::cmp::max(offset, target_offset));
+                if motion.is_inclusive() {
+                    end = std::cmp::min(end + 1, content.typed_offset(content.end_position()));
+                }
+                self.operate(content, operator, cmd.register, start, end, false)
+            },
+            Action::OperateLines(operator) => {
+                let line = content.cursor_pos().0 .0;
+                self.operate_lines(content, operator, cmd.register, (line, line + count - 1))
+            },
+            Action::DeleteChar => {
+                let end = std::cmp::min(offset + count, content.typed_offset(content.end_position()));
+                self.operate(content, Operator::Delete, cmd.register, offset, end, false)
+            },
+            Action::DeleteToEnd | Action::ChangeToEnd => {
+                let mut target = content.clone();
+                target.cursor_end();
+                let end = target.typed_offset(target.cursor());
+                let operator = if cmd.action == Action::DeleteToEnd { Operator::Delete } else { Operator::Change };
+                self.operate(content, operator, cmd.register, offset, std::cmp::max(offset, end), false)
+            },
+            Action::Paste { before } => {
+                let register = self.registers.get(&cmd.register.unwrap_or('"')).cloned().unwrap_or_default();
+                if register.text.is_empty() {
+                    return Outcome::Handled;
+                }
+                let text = register.text.repeat(count);
+                if register.linewise {
+                    let line = content.cursor_pos().0 .0;
+                    let line = if before { line } else { line + 1 };
+                    match content.cursor_for_display_pos((line, 0)) {
+                        Some(pos) if line > 0 || before => {
+                            content.set_cursor(pos);
+                            content.insert_str(&text);
+                        },
+                        _ => {
+                            content.set_cursor(content.end_position());
+                            content.insert('\n');
+                            content.insert_str(text.trim_end_matches('\n'));
+                        },
+                    }
+                    content.set_cursor(content.cursor_for_display_pos((line, 0)).unwrap_or(content.end_position()));
+                } else {
+                    if !before {
+                        content.cursor_right();
+                    }
+                    content.insert_str(&text);
+                    content.cursor_left();
+                }
+                Outcome::Edited
+            },
+            Action::Insert => self.enter_insert_mode(),
+            Action::Append => {
+                content.cursor_right();
+                self.enter_insert_mode()
+            },
+            Action::InsertAtLineStart => {
+                content.cursor_home();
+                self.enter_insert_mode()
+            },
+            Action::AppendAtLineEnd => {
+                content.cursor_end();
+                self.enter_insert_mode()
+            },
+            Action::OpenLine { above } => {
+                if above {
+                    content.cursor_home();
+                    content.insert('\n');
+                    content.cursor_left();
+                } else {
+                    content.cursor_end();
+                    content.insert('\n');
+                }
+                self.enter_insert_mode();
+                Outcome::Edited
+            },
+            Action::Visual => {
+                self.mode = Mode::Visual;
+                self.visual_anchor = offset;
+                Outcome::Handled
+            },
+            Action::Repeat => {
+                let keys = self.last_change.clone();
+                let mut outcome = Outcome::Handled;
+                for _ in 0..count {
+                    for key in &keys {
+                        if self.handle_key(key, content) == Outcome::Edited {
+                            outcome = Outcome::Edited;
+                        }
+                    }
+                }
+                outcome
+            },
+        }
+    }
+
+    fn enter_insert_mode(&mut self) -> Outcome {
+        self.mode = Mode::Insert;
+        Outcome::Handled
+    }
+
+    // typed offsets of the visual selection, including the char under the cursor
+    fn visual_range(&self, content: &Content) -> (usize, usize) {
+        let offset = content.typed_offset(content.cursor());
+        let len = content.typed_offset(content.end_position());
+        let start = std::cmp::min(self.visual_anchor, offset);
+        let end = std::cmp::max(self.visual_anchor, offset);
+        (std::cmp::min(start, len), std::cmp::min(end + 1, len))
+    }
+
+    fn operate(&mut self, content: &mut Content, operator: Operator, register: Option<char>, start: usize, end: usize, linewise: bool) -> Outcome {
+        let start_pos = content.cursor_at_typed_offset(start);
+        let end_pos = content.cursor_at_typed_offset(end);
+        // like Backspace, whitespace that separates tokens isn't deleted
+        if operator != Operator::Yank && content.range_deletion_merges_tokens(start_pos, end_pos) {
+            return Outcome::Handled;
+        }
+        let text: String = content.typed_string().chars().skip(start).take(end - start).collect();
+        let register_content = Register { text, linewise };
+        self.registers.insert('"', register_content.clone());
+        if let Some(r) = register {
+            self.registers.insert(r, register_content);
+        }
+        match operator {
+            Operator::Yank => {
+                content.set_cursor(start_pos);
+                Outcome::Handled
+            },
+            Operator::Delete => {
+                content.delete_range(start_pos, end_pos);
+                Outcome::Edited
+            },
+            Operator::Change => {
+                content.delete_range(start_pos, end_pos);
+                self.mode = Mode::Insert;
+                Outcome::Edited
+            },
+        }
+    }
+
+    // operates on whole display lines
+    fn operate_lines(&mut self, content: &mut Content, operator: Operator, register: Option<char>, lines: (usize, usize)) -> Outcome {
+        let line_start = |line| content.cursor_for_display_pos((line, 0));
+        let start = line_start(lines.0).unwrap_or(content.end_position());
+        let mut end_of_last_line = content.clone();
+        end_of_last_line.set_cursor(line_start(lines.1).unwrap_or(content.end_position()));
+        end_of_last_line.cursor_end();
+        let end_of_last_line = end_of_last_line.cursor();
+
+        let start_offset = content.typed_offset(start);
+        let (start_offset, end_offset) = match line_start(lines.1 + 1) {
+            _ if operator == Operator::Change => (start_offset, content.typed_offset(end_of_last_line)),
+            Some(next) => (start_offset, content.typed_offset(next)),
+            // the last line has no newline to remove, so remove the one in front of it
+            None => {
+                let mut prev = content.clone();
+                prev.set_cursor(start);
+                prev.cursor_left();
+                (if lines.0 > 0 { prev.typed_offset(prev.cursor()) } else { start_offset }, content.typed_offset(end_of_last_line))
+            },
+        };
+        let mut text: String = content.typed_string().chars().skip(start_offset).take(end_offset - start_offset).collect();
+        if operator != Operator::Change {
+            // normalize to "lines\n", independent of which newline got removed
+            text = format!("{}\n", text.trim_start_matches('\n').trim_end_matches('\n'));
+        }
+        let outcome = self.operate(content, operator, register, start_offset, end_offset, operator != Operator::Change);
+        if let Some(r) = self.registers.get_mut(&'"') {
+            r.text = text.clone();
+        }
+        if let Some(r) = register.and_then(|r| self.registers.get_mut(&r)) {
+            r.text = text;
+        }
+        if operator == Operator::Delete {
+            let line = std::cmp::min(lines.0, content.line_numbers().len() - 1);
+            content.set_cursor(content.cursor_for_display_pos((line, 0)).unwrap_or(content.end_position()));
+        }
+        outcome
+    }
+
+    fn apply_line_motion(&self, content: &mut Content, motion: Motion, count: Option<usize>) {
+        let num_lines = content.line_numbers().len();
+        let line = match (motion, count) {
+            (_, Some(n)) => std::cmp::min(n, num_lines) - 1,
+            (Motion::FirstLine, None) => 0,
+            _ => num_lines - 1,
+        };
+        content.set_cursor(content.cursor_for_display_pos((line, 0)).unwrap_or(content.end_position()));
+    }
+
+    fn apply_motion(&self, content: &mut Content, motion: Motion, count: usize) {
+        for _ in 0..count {
+            match motion {
+                Motion::Left => content.cursor_left(),
+                Motion::Right => content.cursor_right(),
+                Motion::Down => content.cursor_down(),
+                Motion::Up => content.cursor_up(),
+                Motion::LineStart => content.cursor_home(),
+                Motion::LineEnd => content.cursor_end(),
+                Motion::WordForward => content.set_cursor(content.word_right_position(WordStop::WordStart)),
+                Motion::WordBackward => content.set_cursor(content.word_left_position(WordStop::WordStart)),
+                Motion::WordEnd => content.set_cursor(content.word_end_position()),
+                Motion::FirstLine | Motion::LastLine => self.apply_line_motion(content, motion, None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(vim: &mut Vim, content: &mut Content, keys: &str) {
+        for c in keys.chars() {
+            let key = match c {
+                '\u{1b}' => KeyChord::plain("Escape"),
+                '\n' => KeyChord::plain("Enter"),
+                c => KeyChord::plain(&c.to_string()),
+            };
+            vim.handle_key(&key, content);
+        }
+    }
+
+    fn offset(content: &Content) -> usize {
+        content.typed_offset(content.cursor())
+    }
+
+    #[test]
+    fn test_word_motions_skip_virtual_whitespace() {
+        let mut c = Content::from_strings("let x=foo(a);", "let x = foo(a);");
+        let mut vim = Vim::default();
+        press(&mut vim, &mut c, "w");
+        assert_eq!(offset(&c), 4);
+        press(&mut vim, &mut c, "w");
+        assert_eq!(offset(&c), 5);  // `=`, separated from `x` by virtual whitespace only
+        press(&mut vim, &mut c, "2w");
+        assert_eq!(offset(&c), 9);
+        press(&mut vim, &mut c, "b");
+        assert_eq!(offset(&c), 6);
+        press(&mut vim, &mut c, "e");
+        assert_eq!(offset(&c), 8);
+    }
+
+    #[test]
+    fn test_delete_word_and_repeat() {
+        let mut c = Content::from_string("a bb ccc d");
+        let mut vim = Vim::default();
+        press(&mut vim, &mut c, "dw");
+        assert_eq!(c.get_string(), "bb ccc d");
+        press(&mut vim, &mut c, "x");
+        assert_eq!(c.get_string(), "b ccc d");
+        press(&mut vim, &mut c, "de");
+        assert_eq!(c.get_string(), " d");
+        press(&mut vim, &mut c, ".");
+        assert_eq!(c.get_string(), "");
+        assert_eq!(vim.register('"').unwrap().text, " d");
+
+        // whitespace that separates tokens is kept
+        let mut c = Content::from_string("return 1");
+        press(&mut vim, &mut c, "6lx");
+        assert_eq!(c.get_string(), "return 1");
+        press(&mut vim, &mut c, "0dw");
+        assert_eq!(c.get_string(), "1");
+
+        // extra cursors stay with the text behind a deletion
+        let mut c = Content::from_string("a\nb\nc");
+        c.add_cursor(c.position_before_char(2));
+        press(&mut vim, &mut c, "dd");
+        assert_eq!(c.get_string(), "b\nc");
+        assert_eq!(c.extra_cursors(), &[c.position_before_char(1)]);
+        assert_eq!(c.extra_cursor_positions().len(), 1);
+    }
+
+    #[test]
+    fn test_change_and_insert() {
+        let mut c = Content::from_string("let foo = 1;");
+        let mut vim = Vim::default();
+        press(&mut vim, &mut c, "wcwbar\u{1b}");
+        assert_eq!(c.get_string(), "let bar = 1;");
+        assert_eq!(vim.mode(), Mode::Normal);
+        press(&mut vim, &mut c, "A // x\u{1b}");
+        assert_eq!(c.get_string(), "let bar = 1; // x");
+        press(&mut vim, &mut c, "0.");
+        assert_eq!(c.get_string(), "let bar = 1; // x // x");
+    }
+
+    #[test]
+    fn test_lines_and_registers() {
+        let mut c = Content::from_string("a\nb\nc");
+        let mut vim = Vim::default();
+        press(&mut vim, &mut c, "j\"xdd");
+        assert_eq!(c.get_string(), "a\nc");
+        assert_eq!(vim.register('x').unwrap(), &Register { text: "b\n".to_string(), linewise: true });
+        press(&mut vim, &mut c, "G\"xp");
+        assert_eq!(c.get_string(), "a\nc\nb");
+        press(&mut vim, &mut c, "ggyyP");
+        assert_eq!(c.get_string(), "a\na\nc\nb");
+        press(&mut vim, &mut c, "Gdk");
+        assert_eq!(c.get_string(), "a\na");
+    }
+
+    #[test]
+    fn test_editing_keys_in_normal_mode() {
+        let mut c = Content::from_string("ab\ncd");
+        let mut vim = Vim::default();
+        for key in &["ArrowRight", "Enter", "Backspace"] {
+            assert_eq!(vim.handle_key(&KeyChord::plain(key), &mut c), Outcome::Handled);
+        }
+        assert_eq!(c.get_string(), "ab\ncd");
+        assert_eq!(offset(&c), 3);
+        assert_eq!(vim.handle_key(&KeyChord::plain("Delete"), &mut c), Outcome::Edited);
+        assert_eq!(c.get_string(), "ab\nd");
+        press(&mut vim, &mut c, "vl");
+        assert_eq!(vim.handle_key(&KeyChord::plain("Delete"), &mut c), Outcome::Edited);
+        assert_eq!(c.get_string(), "ab\n");
+    }
+
+    #[test]
+    fn test_huge_counts() {
+        let mut c = Content::from_string("abc def");
+        let mut vim = Vim::default();
+        press(&mut vim, &mut c, "99999999999999999999999l");
+        assert_eq!(c.cursor(), c.end_position());
+        press(&mut vim, &mut c, "0");
+        press(&mut vim, &mut c, "99999999999d99999999999w");
+        assert_eq!(c.get_string(), "");
+    }
+
+    #[test]
+    fn test_visual_mode() {
+        let mut c = Content::from_string("abcdef");
+        let mut vim = Vim::default();
+        press(&mut vim, &mut c, "lvll");
+        assert_eq!(vim.mode(), Mode::Visual);
+        // from `b` to behind `d`
+        assert_eq!(vim.selection(&c), Some(((1, 0), (4, 0))));
+        press(&mut vim, &mut c, "y");
+        assert_eq!(vim.register('"').unwrap().text, "bcd");
+        press(&mut vim, &mut c, "vd");
+        assert_eq!(c.get_string(), "acdef");
+    }
+}